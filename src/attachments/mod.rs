@@ -0,0 +1,273 @@
+//! Validates and re-encodes uploaded images.
+//!
+//! Images are decoded and encoded again from their pixels, which strips every kind of metadata (EXIF, including GPS coordinates, XMP, comments, etc.) that could identify the poster. The EXIF orientation is applied to the pixels first.
+//!
+//! Uploads that haven't been attached to a post in time are removed by [`clean_up`], along with their images.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::io::{
+	self,
+	Cursor,
+};
+
+use actix_web::web;
+use blake2::digest::consts::U32;
+use blake2::{
+	Blake2b,
+	Digest,
+};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{
+	DynamicImage,
+	ImageDecoder,
+	ImageError,
+	ImageFormat,
+	ImageReader,
+	Limits,
+};
+use mongodb::bson::{
+	doc,
+	Bson,
+	DateTime,
+};
+use mongodb::Database;
+use serde::Serialize;
+
+use crate::conf;
+use crate::storage::Storage;
+use crate::types::{
+	Post,
+	Upload,
+};
+
+/// The formats of processed images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	Jpeg,
+	Png,
+}
+
+impl Format {
+	pub fn extension(self) -> &'static str {
+		match self {
+			Self::Jpeg => "jpg",
+			Self::Png => "png",
+		}
+	}
+
+	pub fn from_extension(extension: &str) -> Option<Self> {
+		match extension {
+			"jpg" => Some(Self::Jpeg),
+			"png" => Some(Self::Png),
+			_ => None,
+		}
+	}
+
+	pub fn content_type(self) -> &'static str {
+		match self {
+			Self::Jpeg => "image/jpeg",
+			Self::Png => "image/png",
+		}
+	}
+}
+
+pub struct ProcessedImage {
+	pub data: Vec<u8>,
+	pub format: Format,
+	pub width: u32,
+	pub height: u32,
+}
+
+impl ProcessedImage {
+	/// The name of the image, derived from its content.
+	pub fn name(&self) -> String {
+		let mut hasher = Blake2b::<U32>::new();
+		hasher.update(&self.data);
+		format!(
+			"{}.{}",
+			hex::encode(hasher.finalize()),
+			self.format.extension()
+		)
+	}
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub enum InvalidImage {
+	/// Not a JPEG, PNG, or WebP image, according to its magic bytes.
+	UnsupportedFormat,
+	/// Smaller or larger than permitted.
+	BadDimensions,
+	/// Couldn't be decoded.
+	Corrupt,
+}
+
+impl fmt::Display for InvalidImage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::UnsupportedFormat => write!(f, "unsupported image format"),
+			Self::BadDimensions => write!(f, "bad image dimensions"),
+			Self::Corrupt => write!(f, "corrupt image"),
+		}
+	}
+}
+
+/// Identifies the format of an image by its magic bytes.
+pub fn sniff(data: &[u8]) -> Option<ImageFormat> {
+	if data.starts_with(b"\xff\xd8\xff") {
+		Some(ImageFormat::Jpeg)
+	} else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+		Some(ImageFormat::Png)
+	} else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+		Some(ImageFormat::WebP)
+	} else {
+		None
+	}
+}
+
+/// Validates an uploaded image, and re-encodes it without metadata.
+///
+/// Images with transparency are encoded as PNG, and all others as JPEG.
+pub fn process(data: &[u8]) -> Result<ProcessedImage, InvalidImage> {
+	let format = sniff(data).ok_or(InvalidImage::UnsupportedFormat)?;
+
+	let mut limits = Limits::default();
+	limits.max_image_width = Some(conf::ATTACHMENT_MAX_DIMENSION);
+	limits.max_image_height = Some(conf::ATTACHMENT_MAX_DIMENSION);
+
+	let mut reader = ImageReader::with_format(Cursor::new(data), format);
+	reader.limits(limits);
+
+	let mut decoder = reader.into_decoder().map_err(from_image_error)?;
+	let (width, height) = decoder.dimensions();
+	if width < conf::ATTACHMENT_MIN_DIMENSION || height < conf::ATTACHMENT_MIN_DIMENSION {
+		return Err(InvalidImage::BadDimensions);
+	}
+
+	let orientation = decoder.orientation().map_err(from_image_error)?;
+	let mut image = DynamicImage::from_decoder(decoder).map_err(from_image_error)?;
+	image.apply_orientation(orientation);
+
+	let mut encoded = Vec::new();
+	let format = if image.color().has_alpha() {
+		image
+			.write_with_encoder(PngEncoder::new(&mut encoded))
+			.map_err(from_image_error)?;
+		Format::Png
+	} else {
+		DynamicImage::ImageRgb8(image.to_rgb8())
+			.write_with_encoder(JpegEncoder::new_with_quality(
+				&mut encoded,
+				conf::ATTACHMENT_JPEG_QUALITY,
+			))
+			.map_err(from_image_error)?;
+		Format::Jpeg
+	};
+
+	Ok(ProcessedImage {
+		data: encoded,
+		format,
+		width: image.width(),
+		height: image.height(),
+	})
+}
+
+fn from_image_error(err: ImageError) -> InvalidImage {
+	match err {
+		ImageError::Limits(_) => InvalidImage::BadDimensions,
+		ImageError::Unsupported(_) => InvalidImage::UnsupportedFormat,
+		_ => InvalidImage::Corrupt,
+	}
+}
+
+/// Uploads created before this time have expired, and can no longer be attached to posts.
+pub fn upload_cutoff() -> DateTime {
+	DateTime::from_millis(
+		DateTime::now().timestamp_millis() - i64::try_from(conf::UPLOAD_TTL.as_millis()).unwrap(),
+	)
+}
+
+/// Deletes the images of expired uploads from storage, except for those still in use.
+pub fn delete_unused(
+	storage: &dyn Storage,
+	names: &[String],
+	in_use: &HashSet<String>,
+) -> io::Result<()> {
+	for name in names {
+		if !in_use.contains(name) {
+			storage.delete(name)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// Removes expired uploads, and their images unless they're attached to a post or were uploaded again since.
+pub async fn clean_up(db: &Database, storage: &'static dyn Storage) -> Result<(), Box<dyn Error>> {
+	let uploads = db.collection::<Upload>("uploads");
+	// uploads are kept for another cleanup interval, so that posts being created with them as they expire are seen
+	let cutoff = DateTime::from_millis(
+		upload_cutoff().timestamp_millis()
+			- i64::try_from(conf::UPLOAD_CLEANUP_INTERVAL.as_millis()).unwrap(),
+	);
+
+	let mut names = uploads
+		.distinct("name", doc! {"created_at": {"$lt": cutoff}}, None)
+		.await?
+		.into_iter()
+		.filter_map(|name| match name {
+			Bson::String(name) => Some(name),
+			_ => None,
+		})
+		.collect::<Vec<String>>();
+
+	if names.is_empty() {
+		return Ok(());
+	}
+
+	let attached = db
+		.collection::<Post>("posts")
+		.distinct(
+			"attachments.name",
+			doc! {"attachments.name": {"$in": &names}},
+			None,
+		)
+		.await?;
+	let uploaded_since = uploads
+		.distinct(
+			"name",
+			doc! {
+				"name": {"$in": &names},
+				"created_at": {"$gte": cutoff},
+			},
+			None,
+		)
+		.await?;
+	let in_use = attached
+		.into_iter()
+		.chain(uploaded_since)
+		.filter_map(|name| match name {
+			Bson::String(name) => Some(name),
+			_ => None,
+		})
+		.collect::<HashSet<String>>();
+
+	names = web::block(move || delete_unused(storage, &names, &in_use).map(|()| names)).await??;
+
+	uploads
+		.delete_many(
+			doc! {
+				"name": {"$in": names},
+				"created_at": {"$lt": cutoff},
+			},
+			None,
+		)
+		.await?;
+
+	Ok(())
+}
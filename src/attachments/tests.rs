@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::Cursor;
+
+use image::{
+	DynamicImage,
+	ImageFormat,
+	RgbImage,
+	RgbaImage,
+};
+
+use super::{
+	delete_unused,
+	process,
+	sniff,
+	Format,
+	InvalidImage,
+};
+use crate::storage::{
+	LocalStorage,
+	Storage,
+};
+
+fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+	let mut data = Vec::new();
+	image.write_to(&mut Cursor::new(&mut data), format).unwrap();
+	data
+}
+
+fn photo(width: u32, height: u32) -> DynamicImage {
+	DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+		image::Rgb([x as u8, y as u8, 128])
+	}))
+}
+
+#[test]
+fn test_sniff() {
+	assert_eq!(
+		sniff(&encode(photo(32, 32), ImageFormat::Jpeg)),
+		Some(ImageFormat::Jpeg)
+	);
+	assert_eq!(
+		sniff(&encode(photo(32, 32), ImageFormat::Png)),
+		Some(ImageFormat::Png)
+	);
+	assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 "), Some(ImageFormat::WebP));
+	assert_eq!(sniff(b"GIF89a"), None);
+	assert_eq!(sniff(b"<svg></svg>"), None);
+}
+
+#[test]
+fn test_strips_exif() {
+	let jpeg = encode(photo(32, 32), ImageFormat::Jpeg);
+
+	// an APP1 segment with an empty big-endian TIFF structure and a marker standing in for GPS data
+	let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0GPS-48.4633,-123.3117".to_vec();
+	let mut with_exif = jpeg[..2].to_vec();
+	with_exif.extend([0xff, 0xe1]);
+	with_exif.extend(u16::try_from(exif.len() + 2).unwrap().to_be_bytes());
+	with_exif.append(&mut exif);
+	with_exif.extend(&jpeg[2..]);
+
+	let processed = process(&with_exif).unwrap();
+	assert_eq!(processed.format, Format::Jpeg);
+	assert_eq!((processed.width, processed.height), (32, 32));
+	assert!(!processed.data.windows(4).any(|w| w == b"Exif"));
+	assert!(!processed.data.windows(3).any(|w| w == b"GPS"));
+}
+
+#[test]
+fn test_keeps_transparency() {
+	let image = DynamicImage::ImageRgba8(RgbaImage::new(20, 20));
+	let processed = process(&encode(image, ImageFormat::Png)).unwrap();
+	assert_eq!(processed.format, Format::Png);
+}
+
+#[test]
+fn test_names_are_content_addressed() {
+	let a = process(&encode(photo(32, 32), ImageFormat::Png)).unwrap();
+	let b = process(&encode(photo(32, 32), ImageFormat::Png)).unwrap();
+	assert_eq!(a.name(), b.name());
+	assert!(a.name().ends_with(".jpg"));
+	assert_eq!(a.name().len(), 64 + 4);
+}
+
+#[test]
+fn test_rejects_bad_dimensions() {
+	assert_eq!(
+		process(&encode(photo(4, 400), ImageFormat::Png)).err(),
+		Some(InvalidImage::BadDimensions)
+	);
+}
+
+#[test]
+fn test_rejects_corrupt() {
+	assert_eq!(
+		process(b"\x89PNG\r\n\x1a\nnot really a png").err(),
+		Some(InvalidImage::Corrupt)
+	);
+}
+
+#[test]
+fn test_delete_unused() {
+	let root = env::temp_dir().join(format!("confesi-uploads-test-{}", std::process::id()));
+	let storage = LocalStorage::new(root.clone()).unwrap();
+	storage.put("expired.jpg", b"expired").unwrap();
+	storage.put("attached.jpg", b"attached").unwrap();
+	storage.put("recent.png", b"recent").unwrap();
+
+	let names = [
+		String::from("expired.jpg"),
+		String::from("attached.jpg"),
+		String::from("missing.jpg"),
+	];
+	let in_use = HashSet::from([String::from("attached.jpg")]);
+	delete_unused(&storage, &names, &in_use).unwrap();
+
+	assert_eq!(storage.get("expired.jpg").unwrap(), None);
+	assert_eq!(
+		storage.get("attached.jpg").unwrap(),
+		Some(b"attached".to_vec())
+	);
+	assert_eq!(storage.get("recent.png").unwrap(), Some(b"recent".to_vec()));
+
+	fs::remove_dir_all(&root).unwrap();
+}
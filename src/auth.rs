@@ -16,7 +16,6 @@ use actix_web::{
 	ResponseError,
 };
 use log::{
-	debug,
 	error,
 	warn,
 };
@@ -39,6 +38,7 @@ use crate::types::{
 	Session,
 	SessionToken,
 	SessionTokenHash,
+	User,
 };
 
 #[derive(Debug)]
@@ -174,9 +174,54 @@ impl FromRequest for AuthenticatedUser {
 	}
 }
 
+/// An authenticated user who can manage schools, having `admin: true` set directly in the database.
+#[derive(Debug)]
+pub struct AdminUser {
+	pub id: ObjectId,
+}
+
+impl FromRequest for AdminUser {
+	type Error = AuthenticationError;
+	type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+	fn from_request(req: &HttpRequest, payload: &mut actix_web::dev::Payload) -> Self::Future {
+		let db = req
+			.app_data::<web::Data<Database>>()
+			.expect("app_data should include a database client")
+			.clone();
+		let user = AuthenticatedUser::from_request(req, payload);
+
+		Box::pin(async move {
+			let user = user.await?;
+			let admins = db
+				.collection::<User>("users")
+				.count_documents(
+					doc! {
+						"_id": {"$eq": user.id},
+						"admin": true,
+					},
+					None,
+				)
+				.await
+				.map_err(|err| {
+					error!("Looking up admin failed: {}", err);
+					AuthenticationError::Unexpected
+				})?;
+
+			if admins == 1 {
+				Ok(AdminUser { id: user.id })
+			} else {
+				Err(AuthenticationError::Forbidden)
+			}
+		})
+	}
+}
+
 #[derive(Debug, Serialize)]
 pub enum AuthenticationError {
 	Unauthenticated,
+	/// The user is authenticated, but isn't an admin.
+	Forbidden,
 	Unexpected,
 }
 
@@ -184,6 +229,7 @@ impl fmt::Display for AuthenticationError {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
 			Self::Unauthenticated => write!(f, "can't perform this action without authentication"),
+			Self::Forbidden => write!(f, "can't perform this action without being an admin"),
 			Self::Unexpected => write!(f, "unexpected error"),
 		}
 	}
@@ -193,6 +239,7 @@ impl ResponseError for AuthenticationError {
 	fn status_code(&self) -> StatusCode {
 		match self {
 			Self::Unauthenticated => StatusCode::UNAUTHORIZED,
+			Self::Forbidden => StatusCode::FORBIDDEN,
 			Self::Unexpected => StatusCode::INTERNAL_SERVER_ERROR,
 		}
 	}
@@ -203,6 +250,9 @@ impl ResponseError for AuthenticationError {
 				.insert_header(("WWW-Authenticate", r#"Bearer realm="user""#))
 				.content_type("application/json")
 				.body(r#"{"error":"Unauthenticated"}"#),
+			Self::Forbidden => HttpResponse::Forbidden()
+				.content_type("application/json")
+				.body(r#"{"error":"Forbidden"}"#),
 			Self::Unexpected => {
 				HttpResponse::InternalServerError().body(r#"{"error":"Unexpected"}"#)
 			}
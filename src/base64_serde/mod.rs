@@ -30,7 +30,7 @@ where
 		}
 
 		fn visit_str<E: Error>(self, s: &str) -> Result<Self::Value, E> {
-			if (N * 4 + 2) / 3 == s.len() {
+			if (N * 4).div_ceil(3) == s.len() {
 				let mut result = [0_u8; N];
 
 				match base64::decode_config_slice(s, base64::URL_SAFE_NO_PAD, &mut result) {
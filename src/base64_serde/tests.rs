@@ -2,6 +2,7 @@ use serde::Deserialize;
 use serde_json::from_str as from_json;
 
 #[derive(Deserialize)]
+#[allow(dead_code)]
 struct Foo(#[serde(with = "super")] [u8; 2]);
 
 #[test]
@@ -0,0 +1,235 @@
+//! Resolves the IP addresses of clients behind reverse proxies.
+//!
+//! Requests from trusted proxies are attributed to the address they forwarded them for, taken from the `Forwarded` header, or `X-Forwarded-For` in its absence. Forwarding headers from any other peer are ignored, since clients can send them too.
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+use std::future;
+use std::net::{
+	IpAddr,
+	SocketAddr,
+};
+use std::str::FromStr;
+
+use actix_web::http::header::{
+	self,
+	HeaderMap,
+};
+use actix_web::http::StatusCode;
+use actix_web::{
+	web,
+	FromRequest,
+	HttpRequest,
+	ResponseError,
+};
+
+/// A range of IP addresses, like `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+	address: IpAddr,
+	prefix_length: u8,
+}
+
+#[derive(Debug)]
+pub struct InvalidCidr;
+
+impl fmt::Display for InvalidCidr {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid CIDR range")
+	}
+}
+
+impl std::error::Error for InvalidCidr {}
+
+impl FromStr for Cidr {
+	type Err = InvalidCidr;
+
+	/// Parses a range in CIDR notation. A lone address is a range of one.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (address, prefix_length) = match s.split_once('/') {
+			Some((address, prefix_length)) => (address, Some(prefix_length)),
+			None => (s, None),
+		};
+		let address = address
+			.parse::<IpAddr>()
+			.map_err(|_| InvalidCidr)?
+			.to_canonical();
+		let max_length = if address.is_ipv4() { 32 } else { 128 };
+		let prefix_length = match prefix_length {
+			Some(prefix_length) => prefix_length.parse::<u8>().map_err(|_| InvalidCidr)?,
+			None => max_length,
+		};
+
+		if prefix_length > max_length {
+			return Err(InvalidCidr);
+		}
+
+		Ok(Self {
+			address,
+			prefix_length,
+		})
+	}
+}
+
+impl Cidr {
+	pub fn contains(&self, address: IpAddr) -> bool {
+		match (self.address, address.to_canonical()) {
+			(IpAddr::V4(range), IpAddr::V4(address)) => {
+				let mask = u32::MAX
+					.checked_shl(32 - u32::from(self.prefix_length))
+					.unwrap_or(0);
+				u32::from(range) & mask == u32::from(address) & mask
+			}
+			(IpAddr::V6(range), IpAddr::V6(address)) => {
+				let mask = u128::MAX
+					.checked_shl(128 - u32::from(self.prefix_length))
+					.unwrap_or(0);
+				u128::from(range) & mask == u128::from(address) & mask
+			}
+			_ => false,
+		}
+	}
+}
+
+/// The reverse proxies whose forwarding headers are believed.
+#[derive(Debug, Default)]
+pub struct TrustedProxies(Vec<Cidr>);
+
+impl FromStr for TrustedProxies {
+	type Err = InvalidCidr;
+
+	/// Parses comma-separated CIDR ranges.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		s.split(',')
+			.map(str::trim)
+			.filter(|range| !range.is_empty())
+			.map(Cidr::from_str)
+			.collect::<Result<Vec<Cidr>, InvalidCidr>>()
+			.map(Self)
+	}
+}
+
+impl TrustedProxies {
+	pub fn is_trusted(&self, address: IpAddr) -> bool {
+		self.0.iter().any(|range| range.contains(address))
+	}
+
+	/// Resolves the client's address from the peer's and the forwarding headers.
+	///
+	/// The forwarding chain is walked back from the peer through trusted proxies. The first untrusted address is the client's. If the chain runs out, or has an address that can't be parsed, the last trusted address is used instead.
+	pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+		let peer = peer.to_canonical();
+		if !self.is_trusted(peer) {
+			return peer;
+		}
+
+		let chain = if headers.contains_key(header::FORWARDED) {
+			forwarded_chain(headers)
+		} else {
+			x_forwarded_for_chain(headers)
+		};
+
+		let mut client = peer;
+		for address in chain.into_iter().rev() {
+			match address {
+				Some(address) => {
+					client = address.to_canonical();
+					if !self.is_trusted(client) {
+						break;
+					}
+				}
+				None => break,
+			}
+		}
+		client
+	}
+}
+
+/// Parses a forwarded address, with an optional port. IPv6 addresses with ports must be in brackets.
+fn parse_node(node: &str) -> Option<IpAddr> {
+	let node = node.trim();
+	node.parse::<IpAddr>()
+		.or_else(|_| node.parse::<SocketAddr>().map(|address| address.ip()))
+		.or_else(|_| {
+			node.strip_prefix('[')
+				.and_then(|node| node.strip_suffix(']'))
+				.ok_or(())
+				.and_then(|node| node.parse::<IpAddr>().map_err(|_| ()))
+		})
+		.ok()
+}
+
+/// Lists the `for` addresses of the `Forwarded` headers, from the client to the last proxy. Addresses that can't be parsed, like `unknown`, are `None`.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+	headers
+		.get_all(header::FORWARDED)
+		.flat_map(|value| match value.to_str() {
+			Ok(value) => value.split(',').map(Some).collect::<Vec<Option<&str>>>(),
+			Err(_) => vec![None],
+		})
+		.map(|element| {
+			element?.split(';').find_map(|pair| {
+				let (name, value) = pair.split_once('=')?;
+				name.trim()
+					.eq_ignore_ascii_case("for")
+					.then(|| parse_node(value.trim().trim_matches('"')))
+			})?
+		})
+		.collect()
+}
+
+/// Lists the addresses of the `X-Forwarded-For` headers, from the client to the last proxy.
+fn x_forwarded_for_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+	headers
+		.get_all("x-forwarded-for")
+		.flat_map(|value| match value.to_str() {
+			Ok(value) => value
+				.split(',')
+				.map(parse_node)
+				.collect::<Vec<Option<IpAddr>>>(),
+			Err(_) => vec![None],
+		})
+		.collect()
+}
+
+/// The IP address of the client, as resolved through [`TrustedProxies`].
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+	/// Resolves the client's address, if the request has a peer address.
+	pub fn resolve(req: &HttpRequest) -> Option<Self> {
+		let trusted_proxies = req
+			.app_data::<web::Data<&'static TrustedProxies>>()
+			.expect("app_data should include trusted proxies");
+
+		req.peer_addr()
+			.map(|peer| Self(trusted_proxies.resolve(peer.ip(), req.headers())))
+	}
+}
+
+#[derive(Debug)]
+pub struct NoPeerAddress;
+
+impl fmt::Display for NoPeerAddress {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "no peer address")
+	}
+}
+
+impl ResponseError for NoPeerAddress {
+	fn status_code(&self) -> StatusCode {
+		StatusCode::INTERNAL_SERVER_ERROR
+	}
+}
+
+impl FromRequest for ClientIp {
+	type Error = NoPeerAddress;
+	type Future = future::Ready<Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+		future::ready(Self::resolve(req).ok_or(NoPeerAddress))
+	}
+}
@@ -0,0 +1,167 @@
+use std::net::IpAddr;
+
+use actix_web::http::header::{
+	HeaderMap,
+	HeaderName,
+	HeaderValue,
+};
+
+use super::{
+	Cidr,
+	TrustedProxies,
+};
+
+fn ip(address: &str) -> IpAddr {
+	address.parse().unwrap()
+}
+
+fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+	let mut headers = HeaderMap::new();
+	for &(name, value) in pairs {
+		headers.append(
+			HeaderName::from_static(name),
+			HeaderValue::from_static(value),
+		);
+	}
+	headers
+}
+
+#[test]
+fn test_cidr() {
+	let range = "10.0.0.0/8".parse::<Cidr>().unwrap();
+	assert!(range.contains(ip("10.1.2.3")));
+	assert!(range.contains(ip("::ffff:10.1.2.3")));
+	assert!(!range.contains(ip("11.0.0.1")));
+	assert!(!range.contains(ip("::1")));
+
+	let range = "fd00::/8".parse::<Cidr>().unwrap();
+	assert!(range.contains(ip("fd12::1")));
+	assert!(!range.contains(ip("fe80::1")));
+
+	assert!("0.0.0.0/0"
+		.parse::<Cidr>()
+		.unwrap()
+		.contains(ip("192.0.2.1")));
+	assert!("192.0.2.1"
+		.parse::<Cidr>()
+		.unwrap()
+		.contains(ip("192.0.2.1")));
+	assert!(!"192.0.2.1"
+		.parse::<Cidr>()
+		.unwrap()
+		.contains(ip("192.0.2.2")));
+
+	assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+	assert!("::/129".parse::<Cidr>().is_err());
+	assert!("10.0.0/8".parse::<Cidr>().is_err());
+}
+
+#[test]
+fn test_trusted_proxies_parse() {
+	assert!("".parse::<TrustedProxies>().unwrap().0.is_empty());
+	assert_eq!(
+		"10.0.0.0/8, ::1".parse::<TrustedProxies>().unwrap().0.len(),
+		2
+	);
+	assert!("10.0.0.0/8,nope".parse::<TrustedProxies>().is_err());
+}
+
+#[test]
+fn test_untrusted_peer() {
+	let proxies = "10.0.0.0/8".parse::<TrustedProxies>().unwrap();
+	let spoofed = headers(&[
+		("forwarded", "for=198.51.100.7"),
+		("x-forwarded-for", "198.51.100.7"),
+	]);
+	assert_eq!(
+		proxies.resolve(ip("203.0.113.9"), &spoofed),
+		ip("203.0.113.9")
+	);
+}
+
+#[test]
+fn test_x_forwarded_for() {
+	let proxies = "10.0.0.0/8".parse::<TrustedProxies>().unwrap();
+	assert_eq!(
+		proxies.resolve(
+			ip("10.0.0.2"),
+			&headers(&[("x-forwarded-for", "198.51.100.7, 203.0.113.9, 10.0.0.1")])
+		),
+		ip("203.0.113.9")
+	);
+	assert_eq!(
+		proxies.resolve(
+			ip("10.0.0.2"),
+			&headers(&[
+				("x-forwarded-for", "198.51.100.7"),
+				("x-forwarded-for", "10.0.0.1")
+			])
+		),
+		ip("198.51.100.7")
+	);
+	// only trusted proxies
+	assert_eq!(
+		proxies.resolve(ip("10.0.0.2"), &headers(&[("x-forwarded-for", "10.0.0.1")])),
+		ip("10.0.0.1")
+	);
+	// no header
+	assert_eq!(
+		proxies.resolve(ip("10.0.0.2"), &HeaderMap::new()),
+		ip("10.0.0.2")
+	);
+	// garbage stops the walk at the last trusted address
+	assert_eq!(
+		proxies.resolve(
+			ip("10.0.0.2"),
+			&headers(&[("x-forwarded-for", "198.51.100.7, garbage, 10.0.0.1")])
+		),
+		ip("10.0.0.1")
+	);
+}
+
+#[test]
+fn test_forwarded() {
+	let proxies = "10.0.0.0/8, fd00::/8".parse::<TrustedProxies>().unwrap();
+	assert_eq!(
+		proxies.resolve(
+			ip("10.0.0.2"),
+			&headers(&[(
+				"forwarded",
+				r#"for=192.0.2.43:47011;proto=https, For="[2001:db8:cafe::17]:4711", for=fd00::1;by=10.0.0.2"#
+			)])
+		),
+		ip("2001:db8:cafe::17")
+	);
+	assert_eq!(
+		proxies.resolve(
+			ip("10.0.0.2"),
+			&headers(&[("forwarded", r#"for="[2001:db8::1]""#)])
+		),
+		ip("2001:db8::1")
+	);
+	// `Forwarded` takes precedence
+	assert_eq!(
+		proxies.resolve(
+			ip("10.0.0.2"),
+			&headers(&[
+				("forwarded", "for=192.0.2.43"),
+				("x-forwarded-for", "198.51.100.7")
+			])
+		),
+		ip("192.0.2.43")
+	);
+	assert_eq!(
+		proxies.resolve(
+			ip("10.0.0.2"),
+			&headers(&[("forwarded", "for=unknown, for=10.0.0.1")])
+		),
+		ip("10.0.0.1")
+	);
+	assert_eq!(
+		proxies.resolve(
+			ip("::ffff:10.0.0.2"),
+			&headers(&[("forwarded", "for=192.0.2.43")])
+		),
+		ip("192.0.2.43")
+	);
+}
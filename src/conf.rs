@@ -1,3 +1,4 @@
+use std::ops::RangeInclusive;
 use std::time::Duration;
 
 /// How long sessions remain usable after their last use.
@@ -9,15 +10,130 @@ pub const SESSION_MIN_TIME_BETWEEN_REFRESH: Duration = Duration::from_secs(3600
 /// The number of posts to return for each request to a post list.
 pub const POSTS_PAGE_SIZE: u16 = 5;
 
+/// The number of schools listed when no `limit` is given.
+pub const SCHOOLS_PAGE_SIZE: u16 = 20;
+
+/// The maximum number of schools listed at once.
+pub const SCHOOLS_PAGE_SIZE_MAX: u16 = 100;
+
+/// The minimum number of users at a school who must share a poster's attributes for them to be shown on posts.
+pub const POSTER_COHORT_MIN_SIZE: u64 = 10;
+
+/// Counts in school statistics below this are withheld, so that they can't single anyone out.
+pub const STATS_MIN_COUNT: i64 = 10;
+
+/// Counts in school statistics are rounded down to a multiple of this.
+pub const STATS_ROUNDING: i64 = 5;
+
+/// The number of faculties listed in school statistics.
+pub const STATS_TOP_FACULTIES: usize = 3;
+
 /// The maximum length of a post in UTF-8 bytes.
 pub const POST_MAX_SIZE: usize = 1000;
 
+/// The maximum length of a search query in UTF-8 bytes.
+pub const SEARCH_QUERY_MAX_SIZE: usize = 200;
+
+/// The maximum number of images attached to a post.
+pub const POST_MAX_ATTACHMENTS: usize = 4;
+
+/// The maximum size of an uploaded image in bytes.
+pub const ATTACHMENT_MAX_SIZE: usize = 10 * 1024 * 1024;
+
+/// The minimum width and height of an image in pixels.
+pub const ATTACHMENT_MIN_DIMENSION: u32 = 16;
+
+/// The maximum width and height of an image in pixels.
+pub const ATTACHMENT_MAX_DIMENSION: u32 = 4096;
+
+/// The quality (1–100) of re-encoded JPEG images.
+pub const ATTACHMENT_JPEG_QUALITY: u8 = 85;
+
+/// The maximum length of an image's alt text in UTF-8 bytes.
+pub const ATTACHMENT_ALT_TEXT_MAX_SIZE: usize = 500;
+
+/// How long uploaded images can be attached to a post for.
+pub const UPLOAD_TTL: Duration = Duration::from_secs(3600 * 24);
+
+/// How often to remove expired uploads and their unattached images.
+pub const UPLOAD_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 15);
+
+/// How long clients and proxies may cache an image. Kept short so that images stop being served soon after their post is taken down.
+pub const ATTACHMENT_CACHE_MAX_AGE: Duration = Duration::from_secs(60 * 5);
+
+/// The maximum number of posts to examine for a page of a post list, when posts are excluded by the user's hidden posts and muted words.
+pub const POSTS_SCAN_LIMIT: u16 = 100;
+
+/// The maximum number of schools a user can watch.
+pub const WATCHED_SCHOOLS_MAX: usize = 10;
+
+/// The maximum number of words a user can mute.
+pub const MUTED_WORDS_MAX: usize = 100;
+
+/// The maximum length of a muted word in UTF-8 bytes.
+pub const MUTED_WORD_MAX_SIZE: usize = 50;
+
+/// The range of the number of options a poll can have.
+pub const POLL_OPTIONS: RangeInclusive<usize> = 2..=6;
+
+/// The maximum length of a poll option in UTF-8 bytes.
+pub const POLL_OPTION_MAX_SIZE: usize = 100;
+
+/// The longest a poll can stay open for.
+pub const POLL_MAX_DURATION: Duration = Duration::from_secs(3600 * 24 * 30);
+
+/// How long the responses to requests with an idempotency key are kept for replaying.
+pub const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(3600 * 24);
+
+/// How long the first request with an idempotency key has to finish before a retry takes the key over, as if the first request never happened. Longer than any request should take.
+pub const IDEMPOTENCY_CLAIM_LEASE: Duration = Duration::from_secs(60);
+
+/// The maximum length of an idempotency key.
+pub const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+
+/// How long users have to wait between requesting exports of their personal data.
+pub const EXPORT_INTERVAL: Duration = Duration::from_secs(3600 * 24);
+
+/// How long personal data exports can be downloaded for.
+pub const EXPORT_TTL: Duration = Duration::from_secs(3600 * 24 * 7);
+
+/// How long an export can take to assemble before it's presumed abandoned, such as by a restart, and marked as failed.
+pub const EXPORT_ASSEMBLY_TIMEOUT: Duration = Duration::from_secs(60 * 10);
+
+/// How often to remove expired exports and fail abandoned ones.
+pub const EXPORT_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+/// How long school email verification codes can be used for.
+pub const VERIFICATION_CODE_TTL: Duration = Duration::from_secs(60 * 15);
+
+/// How long users have to wait between requesting school email verification codes.
+pub const VERIFICATION_RESEND_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The number of wrong guesses allowed for each school email verification code.
+pub const VERIFICATION_MAX_ATTEMPTS: u32 = 5;
+
+/// The maximum length of an email address in bytes.
+pub const EMAIL_ADDRESS_MAX_SIZE: usize = 254;
+
 /// The maximum length of a comment in UTF-8 bytes.
+#[allow(dead_code)] // comments aren't implemented yet
 pub const COMMENT_MAX_SIZE: usize = 500;
 
+/// The maximum length of a display name in grapheme clusters.
+pub const DISPLAY_NAME_MAX_LENGTH: usize = 50;
+
+/// The maximum length of a bio in grapheme clusters.
+pub const BIO_MAX_LENGTH: usize = 150;
+
 /// The maximum length of a username.
 pub const USERNAME_MAX_LENGTH: usize = 32;
 
+/// The maximum length of a school ID, such as `UVIC`.
+pub const SCHOOL_ID_MAX_LENGTH: usize = 16;
+
+/// The maximum length of a school name in grapheme clusters.
+pub const SCHOOL_NAME_MAX_LENGTH: usize = 100;
+
 /// The expected value of the `Host` header. Checked in order to protect unauthenticated endpoints from DNS rebinding.
 pub const HOST: &str = "localhost:3000";
 
@@ -28,3 +144,18 @@ pub const PERMITTED_ORIGINS: &[&str] = &["https://app.invalid", "http://api-docs
 pub const TRENDING_EPOCH: i64 = 1640995200; // 2022-01-01T00:00:00Z
 
 pub const TRENDING_DECAY: f64 = 103616.32918473207; // 45000 ln 10
+
+/// The standard normal quantile of the confidence level used for the "best" ranking's Wilson score interval.
+pub const BEST_CONFIDENCE_Z: f64 = 1.959963984540054; // 95%
+
+/// How far back votes count towards the "rising" ranking.
+pub const RISING_WINDOW: Duration = Duration::from_secs(3600 * 6);
+
+/// How often to check whether the GeoIP database file was replaced.
+pub const GEOIP_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long to wait between recomputing school statistics.
+pub const SCHOOL_STATS_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 15);
+
+/// How long to wait between recomputing "rising" scores, dropping votes that have left [`RISING_WINDOW`].
+pub const RISING_REFRESH_INTERVAL: Duration = Duration::from_secs(60 * 5);
@@ -0,0 +1,196 @@
+//! Screens the text of posts before publication, and of profiles, using rules loaded from a file.
+//!
+//! Each rule matches text with a regular expression or a list of words, and either rejects the post, holds it for review by moderators, or masks the matched text.
+
+#[cfg(test)]
+mod tests;
+
+use std::error::Error;
+use std::fs;
+
+use regex::{
+	Regex,
+	RegexBuilder,
+};
+use serde::{
+	Deserialize,
+	Serialize,
+};
+
+/// The kind of content a rule detects. Reported to users when their post is rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+	PhoneNumber,
+	EmailAddress,
+	StudentNumber,
+	FullName,
+	Profanity,
+	Harassment,
+	Spam,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+	/// Refuse the post.
+	Reject,
+	/// Accept the post, but don't publish it until a moderator has reviewed it.
+	Hold,
+	/// Replace the matched text with asterisks.
+	Mask,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Matcher {
+	/// A regular expression.
+	Pattern(String),
+	/// Words or phrases, matched case-insensitively as whole words.
+	Words(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct RuleConfig {
+	category: Category,
+	action: Action,
+	#[serde(flatten)]
+	matcher: Matcher,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+	rules: Vec<RuleConfig>,
+}
+
+struct Rule {
+	category: Category,
+	action: Action,
+	regex: Regex,
+}
+
+pub struct ContentFilter {
+	rules: Vec<Rule>,
+}
+
+/// The result of screening text that wasn't rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Screened {
+	/// The text, with masked matches replaced.
+	pub text: String,
+	/// The categories of the rules that held the text for review, if any.
+	pub held: Vec<Category>,
+}
+
+/// The text was rejected by a rule of the given category.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rejected(pub Category);
+
+/// Compiles a regex matching any of the given words as whole words, ignoring case.
+pub fn words_regex(words: &[String]) -> Result<Regex, regex::Error> {
+	RegexBuilder::new(&format!(
+		r"\b(?:{})\b",
+		words
+			.iter()
+			.map(|word| regex::escape(word))
+			.collect::<Vec<String>>()
+			.join("|")
+	))
+	.case_insensitive(true)
+	.build()
+}
+
+impl ContentFilter {
+	/// Loads rules from a JSON file.
+	pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+		Self::from_json(&fs::read_to_string(path)?)
+	}
+
+	pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+		let config = serde_json::from_str::<Config>(json)?;
+		let rules = config
+			.rules
+			.into_iter()
+			.map(|rule| {
+				// a rule that matches nothing in particular would match every post
+				let regex = match rule.matcher {
+					Matcher::Pattern(pattern) => {
+						let regex = Regex::new(&pattern)?;
+						if regex.is_match("") {
+							return Err(format!(
+								"{:?} rule pattern matches empty text",
+								rule.category
+							)
+							.into());
+						}
+						regex
+					}
+					Matcher::Words(words) => {
+						if words.is_empty() || words.iter().any(|word| word.trim().is_empty()) {
+							return Err(format!(
+								"{:?} rule has no words, or empty words",
+								rule.category
+							)
+							.into());
+						}
+						words_regex(&words)?
+					}
+				};
+
+				Ok(Rule {
+					category: rule.category,
+					action: rule.action,
+					regex,
+				})
+			})
+			.collect::<Result<Vec<Rule>, Box<dyn Error>>>()?;
+
+		Ok(Self { rules })
+	}
+
+	/// Screens text against every rule. Rejections take precedence over holds, which take precedence over masks.
+	pub fn screen(&self, text: &str) -> Result<Screened, Rejected> {
+		if let Some(rule) = self
+			.rules
+			.iter()
+			.find(|rule| rule.action == Action::Reject && rule.regex.is_match(text))
+		{
+			return Err(Rejected(rule.category));
+		}
+
+		let mut held = Vec::new();
+		let mut text = text.to_owned();
+
+		for rule in &self.rules {
+			match rule.action {
+				Action::Reject => {}
+				Action::Hold => {
+					if rule.regex.is_match(&text) && !held.contains(&rule.category) {
+						held.push(rule.category);
+					}
+				}
+				Action::Mask => {
+					text = rule
+						.regex
+						.replace_all(&text, |captures: &regex::Captures| {
+							"*".repeat(captures[0].chars().count())
+						})
+						.into_owned();
+				}
+			}
+		}
+
+		Ok(Screened { text, held })
+	}
+
+	/// Screens text that moderators won't review, such as profiles. Text that would be held for review is rejected instead.
+	pub fn screen_unreviewed(&self, text: &str) -> Result<String, Rejected> {
+		let screened = self.screen(text)?;
+
+		match screened.held.first() {
+			Some(&category) => Err(Rejected(category)),
+			None => Ok(screened.text),
+		}
+	}
+}
@@ -0,0 +1,124 @@
+use super::{
+	Category,
+	ContentFilter,
+	Rejected,
+	Screened,
+};
+
+const RULES: &str = r#"{
+	"rules": [
+		{"category": "phone_number", "action": "mask", "pattern": "\\b[0-9]{3}-[0-9]{3}-[0-9]{4}\\b"},
+		{"category": "full_name", "action": "hold", "words": ["Jane Doe", "John Smith"]},
+		{"category": "profanity", "action": "reject", "words": ["heck"]}
+	]
+}"#;
+
+#[test]
+fn test_default_rules_load() {
+	assert!(ContentFilter::load("config/content-filter.json").is_ok());
+}
+
+#[test]
+fn test_invalid_rules() {
+	assert!(ContentFilter::from_json(
+		r#"{"rules": [{"category": "spam", "action": "mask", "pattern": "("}]}"#
+	)
+	.is_err());
+	assert!(ContentFilter::from_json(
+		r#"{"rules": [{"category": "unknown", "action": "mask", "pattern": "a"}]}"#
+	)
+	.is_err());
+}
+
+#[test]
+fn test_rules_matching_everything() {
+	for rule in [
+		r#"{"category": "spam", "action": "reject", "words": []}"#,
+		r#"{"category": "spam", "action": "reject", "words": ["spam", " "]}"#,
+		r#"{"category": "spam", "action": "hold", "pattern": ""}"#,
+		r#"{"category": "spam", "action": "mask", "pattern": "x*"}"#,
+	] {
+		let error = ContentFilter::from_json(&format!(r#"{{"rules": [{}]}}"#, rule))
+			.err()
+			.unwrap();
+		assert!(error.to_string().starts_with("Spam rule"), "{}", error);
+	}
+}
+
+#[test]
+fn test_clean_text() {
+	let filter = ContentFilter::from_json(RULES).unwrap();
+	assert_eq!(
+		filter.screen("nothing to see here"),
+		Ok(Screened {
+			text: String::from("nothing to see here"),
+			held: vec![],
+		})
+	);
+}
+
+#[test]
+fn test_mask() {
+	let filter = ContentFilter::from_json(RULES).unwrap();
+	assert_eq!(
+		filter.screen("call me at 250-555-0199!").unwrap().text,
+		"call me at ************!"
+	);
+}
+
+#[test]
+fn test_hold_matches_whole_words_case_insensitively() {
+	let filter = ContentFilter::from_json(RULES).unwrap();
+	assert_eq!(
+		filter.screen("saw JANE DOE in the library").unwrap().held,
+		[Category::FullName]
+	);
+	assert!(filter.screen("saw Jane Doerr").unwrap().held.is_empty());
+}
+
+#[test]
+fn test_reject_takes_precedence() {
+	let filter = ContentFilter::from_json(RULES).unwrap();
+	assert_eq!(
+		filter.screen("what the heck, John Smith, 250-555-0199"),
+		Err(Rejected(Category::Profanity))
+	);
+}
+
+#[test]
+fn test_screen_unreviewed() {
+	let filter = ContentFilter::from_json(RULES).unwrap();
+	assert_eq!(
+		filter.screen_unreviewed("call me at 250-555-0199"),
+		Ok(String::from("call me at ************"))
+	);
+	assert_eq!(
+		filter.screen_unreviewed("Jane Doe"),
+		Err(Rejected(Category::FullName))
+	);
+	assert_eq!(
+		filter.screen_unreviewed("oh heck"),
+		Err(Rejected(Category::Profanity))
+	);
+}
+
+#[test]
+fn test_default_rules_refuse_profane_profiles() {
+	let filter = ContentFilter::load("config/content-filter.json").unwrap();
+	assert_eq!(
+		filter.screen_unreviewed("Fucking Legend"),
+		Err(Rejected(Category::Profanity))
+	);
+	assert_eq!(
+		filter.screen_unreviewed("second year, shitty at chemistry"),
+		Err(Rejected(Category::Profanity))
+	);
+	assert_eq!(
+		filter.screen_unreviewed("Scunthorpe expat"),
+		Ok(String::from("Scunthorpe expat"))
+	);
+	assert_eq!(
+		filter.screen("this is bullshit").unwrap().held,
+		[Category::Profanity]
+	);
+}
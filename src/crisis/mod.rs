@@ -0,0 +1,84 @@
+//! Detects posts indicating self-harm, so that support resources can be shown alongside them and moderators can prioritize reviewing them.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+
+use regex::{
+	Regex,
+	RegexBuilder,
+};
+use serde::Deserialize;
+
+use crate::types::SupportContact;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+	/// Phrases indicating a crisis, by locale.
+	phrases: HashMap<String, Vec<String>>,
+	crisis_lines: Vec<SupportContact>,
+}
+
+pub struct CrisisDetector {
+	/// A pattern matching any phrase, for each locale.
+	patterns: Vec<(String, Regex)>,
+	/// Support lines shown regardless of school.
+	pub crisis_lines: Vec<SupportContact>,
+}
+
+impl CrisisDetector {
+	/// Loads phrase lists and crisis lines from a JSON file.
+	pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+		Self::from_json(&fs::read_to_string(path)?)
+	}
+
+	pub fn from_json(json: &str) -> Result<Self, Box<dyn Error>> {
+		let config = serde_json::from_str::<Config>(json)?;
+		let mut patterns = config
+			.phrases
+			.into_iter()
+			.filter(|(_, phrases)| !phrases.is_empty())
+			.map(|(locale, phrases)| {
+				let alternatives = phrases
+					.iter()
+					.map(|phrase| {
+						phrase
+							.split_whitespace()
+							.map(regex::escape)
+							.collect::<Vec<String>>()
+							.join(r"\s+")
+					})
+					.collect::<Vec<String>>()
+					.join("|");
+
+				let pattern = RegexBuilder::new(&format!(r"\b(?:{})\b", alternatives))
+					.case_insensitive(true)
+					.build()?;
+
+				Ok((locale, pattern))
+			})
+			.collect::<Result<Vec<(String, Regex)>, regex::Error>>()?;
+		patterns.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+		Ok(Self {
+			patterns,
+			crisis_lines: config.crisis_lines,
+		})
+	}
+
+	/// Gets the locales whose phrases appear in the text, if any.
+	pub fn detect(&self, text: &str) -> Vec<&str> {
+		// typographic apostrophes are common on mobile keyboards
+		let text = text.replace('\u{2019}', "'");
+
+		self.patterns
+			.iter()
+			.filter(|(_, pattern)| pattern.is_match(&text))
+			.map(|(locale, _)| locale.as_str())
+			.collect()
+	}
+}
@@ -0,0 +1,37 @@
+use super::CrisisDetector;
+
+fn detector() -> CrisisDetector {
+	CrisisDetector::load("config/crisis-language.json").unwrap()
+}
+
+fn fixtures(fixture: &str) -> impl Iterator<Item = &str> {
+	fixture.lines().filter(|line| !line.is_empty())
+}
+
+#[test]
+fn test_flagged_fixtures() {
+	let detector = detector();
+	for text in fixtures(include_str!("fixtures/flagged.txt")) {
+		assert!(!detector.detect(text).is_empty(), "not flagged: {}", text);
+	}
+}
+
+#[test]
+fn test_unflagged_fixtures() {
+	let detector = detector();
+	for text in fixtures(include_str!("fixtures/unflagged.txt")) {
+		assert!(detector.detect(text).is_empty(), "flagged: {}", text);
+	}
+}
+
+#[test]
+fn test_detects_locale() {
+	let detector = detector();
+	assert_eq!(detector.detect("je veux mourir"), ["fr"]);
+	assert_eq!(detector.detect("I want to die"), ["en"]);
+}
+
+#[test]
+fn test_has_crisis_lines() {
+	assert!(!detector().crisis_lines.is_empty());
+}
@@ -0,0 +1,139 @@
+//! Personal data exports: their archives in storage, and signed links for downloading them without authentication until they expire.
+
+#[cfg(test)]
+mod tests;
+
+use std::error::Error;
+
+use actix_web::web;
+use futures::TryStreamExt;
+use log::error;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{
+	doc,
+	DateTime,
+	Document,
+};
+use mongodb::Database;
+use ring::hmac;
+
+use crate::conf;
+use crate::storage::Storage;
+use crate::types::{
+	Export,
+	ExportStatus,
+};
+
+pub struct LinkSigner(hmac::Key);
+
+impl LinkSigner {
+	pub fn new(key: &[u8; 32]) -> Self {
+		Self(hmac::Key::new(hmac::HMAC_SHA256, key))
+	}
+
+	fn message(export: &ObjectId, expires: i64) -> [u8; 20] {
+		let mut message = [0; 20];
+		message[0..12].copy_from_slice(&export.bytes());
+		message[12..20].copy_from_slice(&expires.to_be_bytes());
+		message
+	}
+
+	/// Signs a link to an export, valid until `expires` (in milliseconds since the Unix epoch).
+	pub fn sign(&self, export: &ObjectId, expires: i64) -> [u8; 32] {
+		hmac::sign(&self.0, &Self::message(export, expires))
+			.as_ref()
+			.try_into()
+			.unwrap()
+	}
+
+	/// Checks a link's signature in constant time. Doesn't check whether it has expired.
+	pub fn verify(&self, export: &ObjectId, expires: i64, signature: &[u8]) -> bool {
+		hmac::verify(&self.0, &Self::message(export, expires), signature).is_ok()
+	}
+}
+
+/// The name of an export's archive in storage.
+pub fn archive_name(export: &ObjectId) -> String {
+	format!("export-{}.json", export.to_hex())
+}
+
+/// Marks an export that's still being assembled as failed, and lets its user request another one right away.
+pub async fn fail(db: &Database, export: ObjectId, user: ObjectId) {
+	let update_result = match db
+		.collection::<Export>("exports")
+		.update_one(
+			doc! {
+				"_id": {"$eq": export},
+				"status": ExportStatus::Pending,
+			},
+			doc! {"$set": {"status": ExportStatus::Failed}},
+			None,
+		)
+		.await
+	{
+		Ok(update_result) => update_result,
+		Err(err) => {
+			error!("Marking export as failed failed: {}", err);
+			return;
+		}
+	};
+
+	if update_result.modified_count != 1 {
+		return;
+	}
+
+	if let Err(err) = db
+		.collection::<Document>("users")
+		.update_one(
+			doc! {"_id": {"$eq": user}},
+			doc! {"$unset": {"last_export_at": true}},
+			None,
+		)
+		.await
+	{
+		error!("Resetting export rate limit failed: {}", err);
+	}
+}
+
+/// Fails exports that have been assembling for longer than [`conf::EXPORT_ASSEMBLY_TIMEOUT`], and removes expired exports along with their archives.
+pub async fn clean_up(db: &Database, storage: &'static dyn Storage) -> Result<(), Box<dyn Error>> {
+	let exports = db.collection::<Export>("exports");
+	let now = DateTime::now();
+	let assembly_cutoff = DateTime::from_millis(
+		now.timestamp_millis() - i64::try_from(conf::EXPORT_ASSEMBLY_TIMEOUT.as_millis()).unwrap(),
+	);
+
+	let abandoned = exports
+		.find(
+			doc! {
+				"status": ExportStatus::Pending,
+				"$expr": {"$lt": [{"$toDate": "$_id"}, assembly_cutoff]},
+			},
+			None,
+		)
+		.await?
+		.try_collect::<Vec<Export>>()
+		.await?;
+
+	for export in abandoned {
+		fail(db, export.id, export.user).await;
+	}
+
+	let expired = exports
+		.find(doc! {"expires_at": {"$lte": now}}, None)
+		.await?
+		.try_collect::<Vec<Export>>()
+		.await?;
+
+	for export in expired {
+		if let Some(archive_name) = export.archive_name {
+			web::block(move || storage.delete(&archive_name)).await??;
+		}
+
+		exports
+			.delete_one(doc! {"_id": {"$eq": export.id}}, None)
+			.await?;
+	}
+
+	Ok(())
+}
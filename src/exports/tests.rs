@@ -0,0 +1,16 @@
+use mongodb::bson::oid::ObjectId;
+
+use super::LinkSigner;
+
+#[test]
+fn test_verify() {
+	let signer = LinkSigner::new(&[7; 32]);
+	let export = ObjectId::new();
+	let signature = signer.sign(&export, 1_700_000_000_000);
+
+	assert!(signer.verify(&export, 1_700_000_000_000, &signature));
+	assert!(!signer.verify(&export, 1_700_000_000_001, &signature));
+	assert!(!signer.verify(&ObjectId::new(), 1_700_000_000_000, &signature));
+	assert!(!signer.verify(&export, 1_700_000_000_000, &signature[1..]));
+	assert!(!LinkSigner::new(&[8; 32]).verify(&export, 1_700_000_000_000, &signature));
+}
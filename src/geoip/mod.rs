@@ -0,0 +1,125 @@
+//! Looks up the locations of IP addresses in a GeoLite2 City database, which can be replaced while the server runs.
+//!
+//! The database file should be replaced by renaming a new file over it, so that lookups in progress keep reading the old one.
+
+#[cfg(test)]
+mod tests;
+
+use std::error::Error;
+use std::fs::{
+	self,
+	File,
+};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::{
+	Arc,
+	RwLock,
+};
+use std::time::SystemTime;
+
+use maxminddb::{
+	geoip2,
+	MaxMindDBError,
+	Reader,
+};
+use memmap::Mmap;
+
+/// Addresses looked up to check that a database can be read.
+const PROBE_ADDRESSES: [IpAddr; 2] = [
+	IpAddr::V4(std::net::Ipv4Addr::new(1, 1, 1, 1)),
+	IpAddr::V6(std::net::Ipv6Addr::new(
+		0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888,
+	)),
+];
+
+/// Checks that a database is a City database that can be read.
+pub fn validate<S: AsRef<[u8]>>(reader: &Reader<S>) -> Result<(), Box<dyn Error>> {
+	if !reader.metadata.database_type.contains("City") {
+		return Err(format!("not a City database: {}", reader.metadata.database_type).into());
+	}
+
+	for address in PROBE_ADDRESSES {
+		match reader.lookup::<geoip2::City>(address) {
+			Ok(_) | Err(MaxMindDBError::AddressNotFoundError(_)) => {}
+			Err(err) => return Err(err.into()),
+		}
+	}
+
+	Ok(())
+}
+
+struct Loaded {
+	reader: Arc<Reader<Mmap>>,
+	/// When the file was last modified as of loading it.
+	modified: SystemTime,
+}
+
+fn load(path: &PathBuf) -> Result<Loaded, Box<dyn Error>> {
+	let file = File::open(path)?;
+	let modified = file.metadata()?.modified()?;
+	let mmap = unsafe { Mmap::map(&file) }?;
+	let reader = Reader::from_source(mmap)?;
+	validate(&reader)?;
+
+	Ok(Loaded {
+		reader: Arc::new(reader),
+		modified,
+	})
+}
+
+/// A GeoIP database that can be reloaded from its file.
+pub struct GeoIp {
+	path: PathBuf,
+	loaded: RwLock<Option<Loaded>>,
+}
+
+impl GeoIp {
+	/// Refers to the database at a path, without loading it yet.
+	pub fn new(path: PathBuf) -> Self {
+		Self {
+			path,
+			loaded: RwLock::new(None),
+		}
+	}
+
+	/// The current database, if one is loaded.
+	pub fn reader(&self) -> Option<Arc<Reader<Mmap>>> {
+		self.loaded
+			.read()
+			.unwrap()
+			.as_ref()
+			.map(|loaded| Arc::clone(&loaded.reader))
+	}
+
+	/// When the current database was built, in seconds since the Unix epoch.
+	pub fn build_epoch(&self) -> Option<u64> {
+		self.loaded
+			.read()
+			.unwrap()
+			.as_ref()
+			.map(|loaded| loaded.reader.metadata.build_epoch)
+	}
+
+	/// Switches to the database in the file, if it's valid. Unless forced, does nothing if the file wasn't modified since it was loaded.
+	///
+	/// Returns whether a database was loaded.
+	pub fn reload(&self, force: bool) -> Result<bool, Box<dyn Error>> {
+		if !force {
+			let modified = fs::metadata(&self.path)?.modified()?;
+			if self
+				.loaded
+				.read()
+				.unwrap()
+				.as_ref()
+				.is_some_and(|loaded| loaded.modified == modified)
+			{
+				return Ok(false);
+			}
+		}
+
+		let loaded = load(&self.path)?;
+		*self.loaded.write().unwrap() = Some(loaded);
+		Ok(true)
+	}
+}
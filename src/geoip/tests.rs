@@ -0,0 +1,154 @@
+use std::env;
+use std::fs::{
+	self,
+	File,
+};
+use std::path::{
+	Path,
+	PathBuf,
+};
+use std::time::{
+	Duration,
+	SystemTime,
+};
+
+use maxminddb::Reader;
+
+use super::GeoIp;
+
+fn temp_path(name: &str) -> PathBuf {
+	env::temp_dir().join(format!(
+		"confesi-geoip-test-{}-{}.mmdb",
+		name,
+		std::process::id()
+	))
+}
+
+/// Encodes the control byte of a MaxMind DB data field, for sizes under 29.
+fn control(data_type: u8, size: usize) -> Vec<u8> {
+	assert!(size < 29);
+	if data_type <= 7 {
+		vec![data_type << 5 | size as u8]
+	} else {
+		vec![size as u8, data_type - 7]
+	}
+}
+
+fn string(value: &str) -> Vec<u8> {
+	[control(2, value.len()), value.as_bytes().to_vec()].concat()
+}
+
+fn unsigned(data_type: u8, value: u64) -> Vec<u8> {
+	let bytes = value.to_be_bytes();
+	let bytes = &bytes[bytes.iter().position(|&b| b != 0).unwrap_or(8)..];
+	[control(data_type, bytes.len()), bytes.to_vec()].concat()
+}
+
+/// Builds an IPv6 database of the given type without any addresses.
+fn empty_database(database_type: &str, build_epoch: u64) -> Vec<u8> {
+	let metadata: [(&str, Vec<u8>); 9] = [
+		("binary_format_major_version", unsigned(5, 2)),
+		("binary_format_minor_version", unsigned(5, 0)),
+		("build_epoch", unsigned(9, build_epoch)),
+		("database_type", string(database_type)),
+		("description", control(7, 0)),
+		("ip_version", unsigned(5, 6)),
+		("languages", control(11, 0)),
+		("node_count", unsigned(6, 1)),
+		("record_size", unsigned(5, 24)),
+	];
+
+	let mut database = Vec::new();
+	// a single node, both of whose records point to the node count: no data
+	database.extend_from_slice(&[0, 0, 1, 0, 0, 1]);
+	database.extend_from_slice(&[0; 16]);
+	database.extend_from_slice(b"\xab\xcd\xefMaxMind.com");
+	database.extend(control(7, metadata.len()));
+	for (key, value) in metadata {
+		database.extend(string(key));
+		database.extend(value);
+	}
+	database
+}
+
+/// Replaces a file by renaming a new one over it, with a distinct modification time.
+fn replace(path: &Path, contents: &[u8], modified: SystemTime) {
+	let new_path = path.with_extension("new");
+	fs::write(&new_path, contents).unwrap();
+	File::options()
+		.write(true)
+		.open(&new_path)
+		.unwrap()
+		.set_modified(modified)
+		.unwrap();
+	fs::rename(&new_path, path).unwrap();
+}
+
+#[test]
+fn test_invalid_database() {
+	assert!(Reader::from_source(b"not a database".to_vec()).is_err());
+
+	let path = temp_path("invalid");
+	fs::write(&path, b"not a database").unwrap();
+
+	let geoip = GeoIp::new(path.clone());
+	assert!(geoip.reload(false).is_err());
+	assert!(geoip.reader().is_none());
+	assert_eq!(geoip.build_epoch(), None);
+
+	assert!(geoip.reload(true).is_err());
+	assert!(geoip.reader().is_none());
+
+	fs::remove_file(&path).unwrap();
+	assert!(geoip.reload(false).is_err());
+}
+
+#[test]
+fn test_valid_replacement() {
+	let path = temp_path("valid");
+	let start = SystemTime::now();
+	replace(&path, &empty_database("GeoLite2-City", 1), start);
+
+	let geoip = GeoIp::new(path.clone());
+	assert!(geoip.reload(true).unwrap());
+	assert_eq!(geoip.build_epoch(), Some(1));
+	assert!(!geoip.reload(false).unwrap());
+
+	replace(
+		&path,
+		&empty_database("GeoLite2-City", 2),
+		start + Duration::from_secs(1),
+	);
+	assert!(geoip.reload(false).unwrap());
+	assert_eq!(geoip.build_epoch(), Some(2));
+	assert!(!geoip.reload(false).unwrap());
+
+	fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_bad_replacement_keeps_database() {
+	let path = temp_path("bad");
+	let start = SystemTime::now();
+	replace(&path, &empty_database("GeoLite2-City", 1), start);
+
+	let geoip = GeoIp::new(path.clone());
+	assert!(geoip.reload(true).unwrap());
+
+	replace(&path, b"not a database", start + Duration::from_secs(1));
+	assert!(geoip.reload(false).is_err());
+	assert_eq!(geoip.build_epoch(), Some(1));
+	assert!(geoip.reader().is_some());
+
+	replace(
+		&path,
+		&empty_database("GeoLite2-Country", 2),
+		start + Duration::from_secs(2),
+	);
+	assert!(geoip.reload(false).is_err());
+	assert_eq!(geoip.build_epoch(), Some(1));
+
+	fs::remove_file(&path).unwrap();
+	assert!(geoip.reload(false).is_err());
+	assert_eq!(geoip.build_epoch(), Some(1));
+}
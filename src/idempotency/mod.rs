@@ -0,0 +1,329 @@
+//! Replays the responses of non-idempotent requests retried with the same `Idempotency-Key` header.
+//!
+//! The first request with a key claims it for the user, along with a fingerprint of the request. Its response is stored once the handler finishes, and retries with the same key get that response back without running the handler again. Keys are forgotten after [`conf::IDEMPOTENCY_KEY_TTL`].
+//!
+//! If the response can't be stored, the claim is released. If the process dies before then, a retry takes the claim over once [`conf::IDEMPOTENCY_CLAIM_LEASE`] has passed.
+
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+use std::future::Future;
+
+use actix_web::body::{
+	self,
+	BoxBody,
+};
+use actix_web::http::header::{
+	self,
+	HeaderValue,
+};
+use actix_web::http::StatusCode;
+use actix_web::{
+	HttpRequest,
+	HttpResponse,
+	Responder,
+	ResponseError,
+};
+use blake2::digest::consts::U32;
+use blake2::{
+	Blake2b,
+	Digest,
+};
+use log::error;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{
+	doc,
+	Bson,
+	DateTime,
+	Document,
+};
+use mongodb::error::{
+	ErrorKind,
+	WriteFailure,
+};
+use mongodb::{
+	Collection,
+	Database,
+};
+use serde::{
+	Deserialize,
+	Serialize,
+};
+
+use crate::api_types::{
+	ApiError,
+	ApiResult,
+	Failure,
+};
+use crate::conf;
+
+pub const KEY_HEADER: &str = "Idempotency-Key";
+
+/// Set on replayed responses.
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// A request made with an idempotency key.
+#[derive(Deserialize, Serialize)]
+pub struct IdempotentRequest {
+	pub user: ObjectId,
+	pub key: String,
+	/// Identifies the method, path and body of the request.
+	pub fingerprint: String,
+	/// Absent while the first request with the key is being handled.
+	pub response: Option<StoredResponse>,
+	pub created_at: DateTime,
+	/// When the request currently being handled with the key started.
+	pub claimed_at: DateTime,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct StoredResponse {
+	pub status: u16,
+	pub body: String,
+}
+
+#[derive(Debug, Serialize)]
+pub enum IdempotencyError {
+	/// The key was already used for a different request.
+	KeyReused,
+	/// The first request with the key hasn't finished yet.
+	InProgress,
+}
+
+impl ApiError for IdempotencyError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::KeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::InProgress => StatusCode::CONFLICT,
+		}
+	}
+}
+
+/// Checks that a key is 1–[`conf::IDEMPOTENCY_KEY_MAX_LENGTH`] visible ASCII characters.
+pub fn is_valid_key(key: &str) -> bool {
+	!key.is_empty()
+		&& key.len() <= conf::IDEMPOTENCY_KEY_MAX_LENGTH
+		&& key.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Hashes what distinguishes a request: its method, its path, and its deserialized body.
+pub fn fingerprint<B: Serialize>(method: &str, path: &str, body: &B) -> String {
+	let mut hasher = Blake2b::<U32>::new();
+	hasher.update(method.as_bytes());
+	hasher.update([0]);
+	hasher.update(path.as_bytes());
+	hasher.update([0]);
+	hasher.update(serde_json::to_vec(body).expect("request bodies should serialize"));
+	hex::encode(hasher.finalize())
+}
+
+/// What to do about a request whose key was already claimed by an earlier request.
+enum Earlier {
+	Replay(StoredResponse),
+	KeyReused,
+	InProgress,
+	/// The earlier request didn't finish within [`conf::IDEMPOTENCY_CLAIM_LEASE`], so its claim can be taken over.
+	Abandoned,
+}
+
+/// Decides what to do about a request with the given fingerprint, made at `now` with the same key as an earlier request.
+fn check_earlier(earlier: IdempotentRequest, fingerprint: &str, now: DateTime) -> Earlier {
+	if earlier.fingerprint != fingerprint {
+		return Earlier::KeyReused;
+	}
+
+	match earlier.response {
+		Some(response) => Earlier::Replay(response),
+		None if now.timestamp_millis() - earlier.claimed_at.timestamp_millis()
+			> i64::try_from(conf::IDEMPOTENCY_CLAIM_LEASE.as_millis()).unwrap() =>
+		{
+			Earlier::Abandoned
+		}
+		None => Earlier::InProgress,
+	}
+}
+
+fn error_response(failure: Failure<IdempotencyError>) -> HttpResponse {
+	failure.error_response()
+}
+
+/// Handles a request by the given user, or replays the response to an earlier request with the same `Idempotency-Key`. Requests without the header are always handled.
+///
+/// Responses with server errors aren't stored, so that the request can be retried.
+pub async fn respond<T, E, B, F>(
+	db: &Database,
+	req: &HttpRequest,
+	user: ObjectId,
+	body: &B,
+	handler: F,
+) -> HttpResponse
+where
+	T: Serialize,
+	E: ApiError + fmt::Debug,
+	B: Serialize,
+	F: Future<Output = ApiResult<T, E>>,
+{
+	let key = match req.headers().get(KEY_HEADER) {
+		None => return render(req, handler.await),
+		Some(key) => match key.to_str() {
+			Ok(key) if is_valid_key(key) => key.to_owned(),
+			_ => return error_response(Failure::BadRequest("bad idempotency key")),
+		},
+	};
+
+	let requests = db.collection::<IdempotentRequest>("idempotent_requests");
+	let fingerprint = fingerprint(req.method().as_str(), req.path(), body);
+	let filter = doc! {
+		"user": {"$eq": user},
+		"key": {"$eq": &key},
+	};
+	let now = DateTime::now();
+
+	match requests
+		.insert_one(
+			IdempotentRequest {
+				user,
+				key: key.clone(),
+				fingerprint: fingerprint.clone(),
+				response: None,
+				created_at: now,
+				claimed_at: now,
+			},
+			None,
+		)
+		.await
+	{
+		Ok(_) => {}
+		Err(err) => match err.kind.as_ref() {
+			ErrorKind::Write(WriteFailure::WriteError(write_err)) if write_err.code == 11000 => {
+				let earlier = match requests.find_one(filter.clone(), None).await {
+					Ok(Some(earlier)) => earlier,
+					// expired in the meantime
+					Ok(None) => {
+						return error_response(Failure::Expected(IdempotencyError::InProgress))
+					}
+					Err(err) => {
+						error!("Finding idempotent request failed: {}", err);
+						return error_response(Failure::Unexpected);
+					}
+				};
+				let claimed_at = earlier.claimed_at;
+
+				match check_earlier(earlier, &fingerprint, now) {
+					Earlier::Replay(response) => return replay(response),
+					Earlier::KeyReused => {
+						return error_response(Failure::Expected(IdempotencyError::KeyReused))
+					}
+					Earlier::InProgress => {
+						return error_response(Failure::Expected(IdempotencyError::InProgress))
+					}
+					Earlier::Abandoned => {
+						// only one retry can take the claim over
+						let mut takeover_filter = filter.clone();
+						takeover_filter.insert("response", Bson::Null);
+						takeover_filter.insert("claimed_at", claimed_at);
+
+						match requests
+							.update_one(takeover_filter, doc! {"$set": {"claimed_at": now}}, None)
+							.await
+						{
+							Ok(update_result) if update_result.modified_count == 1 => {}
+							Ok(_) => {
+								return error_response(Failure::Expected(
+									IdempotencyError::InProgress,
+								))
+							}
+							Err(err) => {
+								error!("Taking over idempotency key failed: {}", err);
+								return error_response(Failure::Unexpected);
+							}
+						}
+					}
+				}
+			}
+			_ => {
+				error!("Claiming idempotency key failed: {}", err);
+				return error_response(Failure::Unexpected);
+			}
+		},
+	}
+
+	let response = render(req, handler.await);
+	// only releases the claim made by this request
+	let mut claim_filter = filter;
+	claim_filter.insert("claimed_at", now);
+
+	if response.status().is_server_error() {
+		release(&requests, claim_filter).await;
+		return response;
+	}
+
+	let (response, body) = response.into_parts();
+	let body = match body::to_bytes(body).await {
+		Ok(body) => body,
+		Err(err) => {
+			error!("Reading response body failed: {}", err);
+			release(&requests, claim_filter).await;
+			return error_response(Failure::Unexpected);
+		}
+	};
+
+	if let Err(err) = requests
+		.update_one(
+			claim_filter.clone(),
+			doc! {"$set": {
+				"response": {
+					"status": i32::from(response.status().as_u16()),
+					"body": String::from_utf8_lossy(&body).into_owned(),
+				},
+			}},
+			None,
+		)
+		.await
+	{
+		error!("Storing idempotent response failed: {}", err);
+		// so that a retry isn't told the request is still in progress; it will be handled again
+		release(&requests, claim_filter).await;
+	}
+
+	response.set_body(BoxBody::new(body))
+}
+
+/// Forgets a claimed key, so that the request can be retried.
+async fn release(requests: &Collection<IdempotentRequest>, claim_filter: Document) {
+	if let Err(err) = requests.delete_one(claim_filter, None).await {
+		error!("Releasing idempotency key failed: {}", err);
+	}
+}
+
+fn render<T: Serialize, E: ApiError + fmt::Debug>(
+	req: &HttpRequest,
+	result: ApiResult<T, E>,
+) -> HttpResponse {
+	match result {
+		Ok(success) => success.respond_to(req).map_into_boxed_body(),
+		Err(failure) => failure.error_response(),
+	}
+}
+
+fn replay(response: StoredResponse) -> HttpResponse {
+	let status = match StatusCode::from_u16(response.status) {
+		Ok(status) => status,
+		Err(err) => {
+			error!("Replaying idempotent response failed: {}", err);
+			return error_response(Failure::Unexpected);
+		}
+	};
+
+	let mut replayed = HttpResponse::with_body(status, response.body).map_into_boxed_body();
+	replayed.headers_mut().insert(
+		header::CONTENT_TYPE,
+		HeaderValue::from_static("application/json"),
+	);
+	replayed.headers_mut().insert(
+		header::HeaderName::from_static(REPLAYED_HEADER),
+		HeaderValue::from_static("true"),
+	);
+	replayed
+}
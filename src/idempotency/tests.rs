@@ -0,0 +1,77 @@
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+
+use super::{
+	check_earlier,
+	fingerprint,
+	is_valid_key,
+	Earlier,
+	IdempotentRequest,
+	StoredResponse,
+};
+use crate::conf;
+
+#[test]
+fn test_is_valid_key() {
+	assert!(is_valid_key("6c1f2a9e-3b4d-4e8f-9a0b-1c2d3e4f5a6b"));
+	assert!(!is_valid_key(""));
+	assert!(!is_valid_key("with space"));
+	assert!(!is_valid_key("naïve"));
+	assert!(!is_valid_key(&"k".repeat(256)));
+}
+
+#[test]
+fn test_fingerprint() {
+	let create = fingerprint("POST", "/posts/", &1);
+
+	assert_eq!(create, fingerprint("POST", "/posts/", &1));
+	assert_ne!(create, fingerprint("POST", "/posts/", &-1));
+	assert_ne!(create, fingerprint("PUT", "/posts/", &1));
+	assert_ne!(create, fingerprint("POST", "/posts/x/vote", &1));
+}
+
+fn earlier(claimed_at: DateTime, response: Option<StoredResponse>) -> IdempotentRequest {
+	IdempotentRequest {
+		user: ObjectId::new(),
+		key: String::from("key"),
+		fingerprint: String::from("fingerprint"),
+		response,
+		created_at: claimed_at,
+		claimed_at,
+	}
+}
+
+#[test]
+fn test_check_earlier() {
+	let now = DateTime::now();
+	let lease = i64::try_from(conf::IDEMPOTENCY_CLAIM_LEASE.as_millis()).unwrap();
+	let recently = DateTime::from_millis(now.timestamp_millis() - lease + 1000);
+	let long_ago = DateTime::from_millis(now.timestamp_millis() - lease - 1000);
+	let response = || {
+		Some(StoredResponse {
+			status: 200,
+			body: String::from("{}"),
+		})
+	};
+
+	assert!(matches!(
+		check_earlier(earlier(recently, None), "fingerprint", now),
+		Earlier::InProgress
+	));
+	assert!(matches!(
+		check_earlier(earlier(long_ago, None), "fingerprint", now),
+		Earlier::Abandoned
+	));
+	assert!(matches!(
+		check_earlier(earlier(long_ago, response()), "fingerprint", now),
+		Earlier::Replay(StoredResponse { status: 200, .. })
+	));
+	assert!(matches!(
+		check_earlier(earlier(recently, response()), "other", now),
+		Earlier::KeyReused
+	));
+	assert!(matches!(
+		check_earlier(earlier(long_ago, None), "other", now),
+		Earlier::KeyReused
+	));
+}
@@ -1,33 +1,57 @@
 #![allow(unused_parens)]
 
 mod api_types;
+mod attachments;
 mod auth;
 mod base64_serde;
+mod client_ip;
 mod conf;
+mod content_filter;
+mod crisis;
+mod exports;
+mod geoip;
+mod idempotency;
+mod mail;
 mod masked_oid;
 mod middleware;
+mod ranking;
+mod school_stats;
+mod schools;
+mod search;
 mod services;
+mod storage;
 mod types;
+mod verification;
 
 use std::env;
 use std::error::Error;
-use std::fs::File;
+use std::time::Duration;
 
 use actix_cors::Cors;
 use actix_web::http::header;
 use actix_web::middleware::Logger;
+use actix_web::rt::signal::unix::{
+	signal,
+	SignalKind,
+};
+use actix_web::rt::time;
 use actix_web::web;
 use actix_web::{
 	App,
 	HttpServer,
 };
+use futures::future::try_join_all;
 use futures::try_join;
 use log::{
+	debug,
+	error,
 	info,
 	warn,
 };
-use memmap::Mmap;
-use mongodb::bson::doc;
+use mongodb::bson::{
+	doc,
+	Document,
+};
 use mongodb::options::{
 	Collation,
 	CollationStrength,
@@ -41,17 +65,39 @@ use mongodb::{
 	IndexModel,
 };
 
+use crate::client_ip::TrustedProxies;
+use crate::content_filter::ContentFilter;
+use crate::crisis::CrisisDetector;
+use crate::exports::LinkSigner;
+use crate::geoip::GeoIp;
+use crate::idempotency::IdempotentRequest;
+use crate::mail::{
+	FileMailer,
+	Mailer,
+	SmtpMailer,
+};
 use crate::masked_oid::MaskingKey;
 use crate::middleware::HostCheckWrap;
+use crate::storage::{
+	LocalStorage,
+	Storage,
+};
 use crate::types::{
+	EmailVerification,
+	Export,
+	Hide,
+	PollVote,
 	Post,
+	PostStatus,
+	Review,
+	Save,
 	School,
 	Session,
+	Upload,
 	User,
 	Vote,
 };
-
-pub type GeoIpReader = &'static maxminddb::Reader<Mmap>;
+use crate::verification::AddressHasher;
 
 async fn initialize_database(db: &Database) -> mongodb::error::Result<()> {
 	let schools = db.collection::<School>("schools");
@@ -59,6 +105,14 @@ async fn initialize_database(db: &Database) -> mongodb::error::Result<()> {
 	let sessions = db.collection::<Session>("sessions");
 	let posts = db.collection::<Post>("posts");
 	let votes = db.collection::<Vote>("votes");
+	let reviews = db.collection::<Review>("reviews");
+	let uploads = db.collection::<Upload>("uploads");
+	let poll_votes = db.collection::<PollVote>("poll_votes");
+	let saves = db.collection::<Save>("saves");
+	let hides = db.collection::<Hide>("hides");
+	let exports = db.collection::<Export>("exports");
+	let email_verifications = db.collection::<EmailVerification>("email_verifications");
+	let idempotent_requests = db.collection::<IdempotentRequest>("idempotent_requests");
 
 	try_join!(
 		users.create_index(
@@ -78,6 +132,32 @@ async fn initialize_database(db: &Database) -> mongodb::error::Result<()> {
 				.build(),
 			None,
 		),
+		// for sizing poster cohorts
+		// an address can only verify one account
+		users.create_index(
+			IndexModel::builder()
+				.keys(doc! {"verified_email_hash": 1})
+				.options(
+					IndexOptions::builder()
+						.unique(true)
+						.partial_filter_expression(doc! {"verified_email_hash": {"$type": "string"}})
+						.build()
+				)
+				.build(),
+			None,
+		),
+		users.create_index(
+			IndexModel::builder()
+				.keys(doc! {"school_id": 1, "faculty": 1, "year_of_study": 1})
+				.build(),
+			None,
+		),
+		users.create_index(
+			IndexModel::builder()
+				.keys(doc! {"school_id": 1, "year_of_study": 1})
+				.build(),
+			None,
+		),
 		sessions.create_index(IndexModel::builder().keys(doc! {"user": 1}).build(), None,),
 		sessions.create_index(
 			IndexModel::builder()
@@ -102,12 +182,122 @@ async fn initialize_database(db: &Database) -> mongodb::error::Result<()> {
 				.build(),
 			None,
 		),
+		posts.create_index(
+			IndexModel::builder().keys(doc! {"text": "text"}).build(),
+			None,
+		),
 		posts.create_index(
 			IndexModel::builder()
-				.keys(doc! {"trending_score": -1})
+				.keys(doc! {"owner": 1, "sequential_id": -1})
 				.build(),
 			None,
 		),
+		// posts created before their school was recorded
+		posts.aggregate(
+			[
+				doc! {"$match": {"school_id": {"$exists": false}}},
+				doc! {"$lookup": {
+					"from": "users",
+					"localField": "owner",
+					"foreignField": "_id",
+					"as": "owner_user",
+				}},
+				// posts whose owner no longer exists can't be attributed to a school, so they're given one that can't match any, and taken down like deleted posts
+				doc! {"$replaceWith": {"$cond": {
+					"if": {"$eq": ["$owner_user", []]},
+					"then": {"_id": "$_id", "school_id": "", "status": PostStatus::Deleted},
+					"else": {"_id": "$_id", "school_id": {"$first": "$owner_user.school_id"}},
+				}}},
+				doc! {"$merge": {
+					"into": "posts",
+					"on": "_id",
+					"whenMatched": "merge",
+					"whenNotMatched": "discard",
+				}},
+			],
+			None,
+		),
+		// users who predate their statistics
+		users.aggregate(
+			[
+				doc! {"$match": {"karma": {"$exists": false}}},
+				doc! {"$lookup": {
+					"from": "posts",
+					"localField": "_id",
+					"foreignField": "owner",
+					"pipeline": [
+						{"$group": {
+							"_id": null,
+							"post_count": {"$sum": 1},
+							"upvotes_received": {"$sum": "$votes_up"},
+							"karma": {"$sum": "$absolute_score"},
+						}},
+					],
+					"as": "stats",
+				}},
+				doc! {"$project": {
+					"post_count": {"$toLong": {"$ifNull": [{"$first": "$stats.post_count"}, 0]}},
+					"upvotes_received": {"$toLong": {"$ifNull": [{"$first": "$stats.upvotes_received"}, 0]}},
+					"karma": {"$toLong": {"$ifNull": [{"$first": "$stats.karma"}, 0]}},
+				}},
+				doc! {"$merge": {
+					"into": "users",
+					"on": "_id",
+					"whenMatched": "merge",
+					"whenNotMatched": "discard",
+				}},
+			],
+			None,
+		),
+		posts.update_many(
+			doc! {"status": {"$exists": false}},
+			doc! {"$set": {"status": PostStatus::Visible}},
+			None,
+		),
+		try_join_all(ranking::ALL.iter().map(|ranking| {
+			posts.create_index(
+				IndexModel::builder()
+					.keys(doc! {ranking.score_field(): -1})
+					.build(),
+				None,
+			)
+		})),
+		// for feeds of visible posts in every order, unfiltered or filtered by school, faculty or year of study
+		try_join_all(
+			ranking::ALL
+				.iter()
+				.map(|ranking| ranking.score_field())
+				.chain(["sequential_id"])
+				.flat_map(|sort_field| {
+					[None, Some("school_id"), Some("faculty"), Some("year_of_study")]
+						.into_iter()
+						.map(move |filter_field| {
+							let mut keys = doc! {"status": 1};
+							if let Some(filter_field) = filter_field {
+								keys.insert(filter_field, 1);
+							}
+							keys.insert(sort_field, -1);
+							keys
+						})
+				})
+				.map(|keys| posts.create_index(IndexModel::builder().keys(keys).build(), None)),
+		),
+		// scores of rankings added after a post was created
+		posts.update_many(
+			doc! {
+				"$or": ranking::ALL
+					.iter()
+					.map(|ranking| doc! {ranking.score_field(): {"$exists": false}})
+					.collect::<Vec<Document>>(),
+			},
+			vec![doc! {
+				"$set": ranking::ALL
+					.iter()
+					.map(|ranking| (ranking.score_field().to_owned(), ranking.vote_update(&ranking::VoteChange::default())))
+					.collect::<Document>(),
+			}],
+			None,
+		),
 		async {
 			schools
 				.create_index(
@@ -118,39 +308,81 @@ async fn initialize_database(db: &Database) -> mongodb::error::Result<()> {
 				)
 				.await?;
 
-			schools
-				.update_one(
+			let seeds = [
+				(
+					"UVIC",
 					doc! {
-						"_id": {"$eq": "UVIC"},
+						"name": "University of Victoria",
+						"position": {
+							"type": "Point",
+							"coordinates": [-123.3117, 48.4633],
+						},
 					},
 					doc! {
-						"$set": {
-							"name": "University of Victoria",
-							"position": {
-								"type": "Point",
-								"coordinates": [-123.3117, 48.4633],
-							},
+						"name": "UVic Counselling Services",
+						"phone": "250-721-8341",
+						"url": "https://www.uvic.ca/services/counselling/",
+					},
+				),
+				(
+					"UBC",
+					doc! {
+						"name": "University of British Columbia",
+						"position": {
+							"type": "Point",
+							"coordinates": [-123.2460, 49.2606],
 						},
 					},
-					UpdateOptions::builder().upsert(true).build(),
+					doc! {
+						"name": "UBC Counselling Services",
+						"phone": "604-822-3811",
+						"url": "https://students.ubc.ca/health/counselling-services",
+					},
+				),
+			];
+
+			for (id, school, counselling) in seeds {
+				// only seeds the schools, which are managed by admins afterwards
+				schools
+					.update_one(
+						doc! {
+							"_id": {"$eq": id},
+						},
+						doc! {
+							"$setOnInsert": school,
+						},
+						UpdateOptions::builder().upsert(true).build(),
+					)
+					.await?;
+
+				// seeded schools from before counselling services were added
+				schools
+					.update_one(
+						doc! {
+							"_id": {"$eq": id},
+							"counselling": {"$exists": false},
+						},
+						doc! {
+							"$set": {"counselling": counselling},
+						},
+						None,
+					)
+					.await?;
+			}
+
+			schools::backfill_search_keys(db).await?;
+
+			schools
+				.create_index(
+					IndexModel::builder().keys(doc! {"search_keys": 1}).build(),
+					None,
 				)
 				.await?;
 
 			schools
-				.update_one(
-					doc! {
-						"_id": {"$eq": "UBC"},
-					},
-					doc! {
-						"$set": {
-							"name": "University of British Columbia",
-							"position": {
-								"type": "Point",
-								"coordinates": [-123.2460, 49.2606],
-							},
-						},
-					},
-					UpdateOptions::builder().upsert(true).build(),
+				.create_index(
+					IndexModel::builder().keys(doc! {"parent_id": 1}).build(),
+					None,
 				)
 				.await?;
 
@@ -163,22 +395,138 @@ async fn initialize_database(db: &Database) -> mongodb::error::Result<()> {
 				.build(),
 			None,
 		),
+		votes.create_index(
+			IndexModel::builder().keys(doc! {"updated_at": 1}).build(),
+			None,
+		),
+		votes.create_index(
+			IndexModel::builder()
+				.keys(doc! {"user": 1, "_id": -1})
+				.build(),
+			None,
+		),
+		poll_votes.create_index(
+			IndexModel::builder()
+				.keys(doc! {"post": 1, "user": 1})
+				.options(IndexOptions::builder().unique(true).build())
+				.build(),
+			None,
+		),
+		saves.create_index(
+			IndexModel::builder()
+				.keys(doc! {"user": 1, "post": 1})
+				.options(IndexOptions::builder().unique(true).build())
+				.build(),
+			None,
+		),
+		saves.create_index(
+			IndexModel::builder()
+				.keys(doc! {"user": 1, "_id": -1})
+				.build(),
+			None,
+		),
+		hides.create_index(
+			IndexModel::builder()
+				.keys(doc! {"user": 1, "post": 1})
+				.options(IndexOptions::builder().unique(true).build())
+				.build(),
+			None,
+		),
+		idempotent_requests.create_index(
+			IndexModel::builder()
+				.keys(doc! {"user": 1, "key": 1})
+				.options(IndexOptions::builder().unique(true).build())
+				.build(),
+			None,
+		),
+		idempotent_requests.create_index(
+			IndexModel::builder()
+				.keys(doc! {"created_at": 1})
+				.options(
+					IndexOptions::builder()
+						.expire_after(conf::IDEMPOTENCY_KEY_TTL)
+						.build()
+				)
+				.build(),
+			None,
+		),
+		// expired exports are removed by `exports::clean_up` rather than a TTL index, so that their archives are removed too
+		exports.create_index(
+			IndexModel::builder().keys(doc! {"expires_at": 1}).build(),
+			None,
+		),
+		email_verifications.create_index(
+			IndexModel::builder()
+				.keys(doc! {"expires_at": 1})
+				.options(IndexOptions::builder().expire_after(Duration::ZERO).build())
+				.build(),
+			None,
+		),
+		reviews.create_index(
+			IndexModel::builder().keys(doc! {"created_at": 1}).build(),
+			None,
+		),
+		// the review queue, urgent reviews first
+		reviews.create_index(
+			IndexModel::builder()
+				.keys(doc! {"urgent": -1, "created_at": 1})
+				.build(),
+			None,
+		),
+		// for checking who can see an image
+		posts.create_index(
+			IndexModel::builder()
+				.keys(doc! {"attachments.name": 1})
+				.build(),
+			None,
+		),
+		uploads.create_index(
+			IndexModel::builder()
+				.keys(doc! {"owner": 1, "name": 1})
+				.options(IndexOptions::builder().unique(true).build())
+				.build(),
+			None,
+		),
+		// expired uploads are removed by `attachments::clean_up` rather than a TTL index, so that their images are removed too
+		uploads.create_index(
+			IndexModel::builder().keys(doc! {"created_at": 1}).build(),
+			None,
+		),
+		// votes cast before they were timestamped
+		votes.update_many(
+			doc! {"updated_at": {"$exists": false}},
+			vec![doc! {"$set": {"updated_at": {"$toDate": "$_id"}}}],
+			None,
+		),
 	)?;
 
 	Ok(())
 }
 
-fn open_geoip_database() -> Result<GeoIpReader, Box<dyn Error>> {
-	let geoip_file = File::open("GeoLite2-City.mmdb")?;
-	let geoip_mmap = unsafe { Mmap::map(&geoip_file) }?;
-	let reader = maxminddb::Reader::from_source(geoip_mmap)?;
-	Ok(Box::leak(Box::new(reader)))
+/// Imports schools from a dataset, printing what changed.
+async fn import_schools(path: &str) -> Result<(), Box<dyn Error>> {
+	let records = schools::load(path)?;
+
+	let db = MongoClient::with_uri_str(env::var("DB_CONNECT")?)
+		.await?
+		.default_database()
+		.expect("no default database");
+	initialize_database(&db).await?;
+
+	println!("{}", schools::import(&db, &records).await?);
+	Ok(())
 }
 
 #[actix_web::main]
 async fn main() -> Result<(), Box<dyn Error>> {
 	env_logger::init_from_env(env_logger::Env::default());
 
+	match env::args().skip(1).collect::<Vec<String>>().as_slice() {
+		[] => {}
+		[command, path] if command == "import-schools" => return import_schools(path).await,
+		_ => return Err("usage: confesi-server [import-schools <file>]".into()),
+	}
+
 	let masking_key: &'static MaskingKey = {
 		let mut key_bytes = [0; 16];
 		// TODO: read this from `/run/secrets` instead
@@ -186,6 +534,71 @@ async fn main() -> Result<(), Box<dyn Error>> {
 		Box::leak(Box::new(MaskingKey::new(&key_bytes)))
 	};
 
+	let link_signer: &'static LinkSigner = {
+		let mut key_bytes = [0; 32];
+		hex::decode_to_slice(env::var("EXPORT_SECRET")?, &mut key_bytes)?;
+		Box::leak(Box::new(LinkSigner::new(&key_bytes)))
+	};
+
+	let address_hasher: &'static AddressHasher = {
+		let mut key_bytes = [0; 32];
+		hex::decode_to_slice(env::var("VERIFICATION_SECRET")?, &mut key_bytes)?;
+		Box::leak(Box::new(AddressHasher::new(&key_bytes)))
+	};
+
+	let content_filter: &'static ContentFilter = Box::leak(Box::new(
+		ContentFilter::load(
+			&env::var("CONTENT_FILTER_RULES")
+				.unwrap_or_else(|_| String::from("config/content-filter.json")),
+		)
+		.map_err(|err| format!("Failed to load content filter rules: {}", err))?,
+	));
+
+	let crisis_detector: &'static CrisisDetector = Box::leak(Box::new(
+		CrisisDetector::load(
+			&env::var("CRISIS_LANGUAGE_RULES")
+				.unwrap_or_else(|_| String::from("config/crisis-language.json")),
+		)
+		.map_err(|err| format!("Failed to load crisis language rules: {}", err))?,
+	));
+
+	let trusted_proxies: &'static TrustedProxies = Box::leak(Box::new(
+		env::var("TRUSTED_PROXIES")
+			.map(|ranges| ranges.parse())
+			.unwrap_or_else(|_| Ok(TrustedProxies::default()))
+			.map_err(|err| format!("Failed to parse trusted proxies: {}", err))?,
+	));
+
+	let school_records = env::var("SCHOOLS_IMPORT")
+		.ok()
+		.map(|path| {
+			schools::load(&path)
+				.map_err(|err| format!("Failed to load schools from {}: {}", path, err))
+		})
+		.transpose()?;
+
+	let storage: &'static dyn Storage = Box::leak(Box::new(LocalStorage::new(
+		env::var("ATTACHMENTS_DIR")
+			.unwrap_or_else(|_| String::from("attachments"))
+			.into(),
+	)?));
+
+	let mail_from = env::var("MAIL_FROM")
+		.unwrap_or_else(|_| String::from("Confesi <no-reply@confesi.invalid>"))
+		.parse()
+		.map_err(|err| format!("Invalid MAIL_FROM: {}", err))?;
+	let mailer: &'static dyn Mailer = match env::var("SMTP_URL") {
+		Ok(url) => Box::leak(Box::new(SmtpMailer::new(&url, mail_from)?)),
+		Err(_) => {
+			let directory = env::var("MAIL_DIR").unwrap_or_else(|_| String::from("mail"));
+			warn!(
+				"SMTP_URL isn't set; writing emails to {} instead",
+				directory
+			);
+			Box::leak(Box::new(FileMailer::new(directory.into(), mail_from)?))
+		}
+	};
+
 	info!("Initializing database");
 
 	let mongo_client = MongoClient::with_uri_str(env::var("DB_CONNECT")?).await?;
@@ -200,15 +613,104 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 	initialize_database(&db).await?;
 
+	if let Some(school_records) = school_records {
+		info!(
+			"Imported schools:\n{}",
+			schools::import(&db, &school_records).await?
+		);
+	}
+
 	info!("Database initialized");
 
-	let geoip_reader = match open_geoip_database() {
-		Ok(geoip_reader) => Some(geoip_reader),
-		Err(err) => {
-			warn!("Failed to open GeoIP database: {}", err);
-			None
+	{
+		let db = db.clone();
+		actix_web::rt::spawn(async move {
+			let mut interval = time::interval(conf::RISING_REFRESH_INTERVAL);
+			loop {
+				interval.tick().await;
+				if let Err(err) = ranking::refresh_rising(&db).await {
+					error!("Refreshing rising scores failed: {}", err);
+				}
+			}
+		});
+	}
+
+	{
+		let db = db.clone();
+		actix_web::rt::spawn(async move {
+			let mut interval = time::interval(conf::SCHOOL_STATS_REFRESH_INTERVAL);
+			loop {
+				interval.tick().await;
+				if let Err(err) = school_stats::refresh(&db).await {
+					error!("Refreshing school stats failed: {}", err);
+				}
+			}
+		});
+	}
+
+	{
+		let db = db.clone();
+		actix_web::rt::spawn(async move {
+			let mut interval = time::interval(conf::EXPORT_CLEANUP_INTERVAL);
+			loop {
+				interval.tick().await;
+				if let Err(err) = exports::clean_up(&db, storage).await {
+					error!("Cleaning up exports failed: {}", err);
+				}
+			}
+		});
+	}
+
+	{
+		let db = db.clone();
+		actix_web::rt::spawn(async move {
+			let mut interval = time::interval(conf::UPLOAD_CLEANUP_INTERVAL);
+			loop {
+				interval.tick().await;
+				if let Err(err) = attachments::clean_up(&db, storage).await {
+					error!("Cleaning up uploads failed: {}", err);
+				}
+			}
+		});
+	}
+
+	let geoip: &'static GeoIp = Box::leak(Box::new(GeoIp::new(
+		env::var("GEOIP_DATABASE")
+			.unwrap_or_else(|_| String::from("GeoLite2-City.mmdb"))
+			.into(),
+	)));
+
+	if let Err(err) = geoip.reload(true) {
+		warn!("Failed to open GeoIP database: {}", err);
+	}
+
+	actix_web::rt::spawn(async move {
+		let mut interval = time::interval(conf::GEOIP_RELOAD_INTERVAL);
+		loop {
+			interval.tick().await;
+			match geoip.reload(false) {
+				Ok(true) => info!("Reloaded GeoIP database built at {:?}", geoip.build_epoch()),
+				Ok(false) => {}
+				// already warned about at startup, such as when no database is installed
+				Err(err) if geoip.reader().is_none() => {
+					debug!("Reloading GeoIP database failed: {}", err)
+				}
+				Err(err) => warn!("Reloading GeoIP database failed: {}", err),
+			}
 		}
-	};
+	});
+
+	{
+		let mut hangups = signal(SignalKind::hangup())?;
+		actix_web::rt::spawn(async move {
+			while hangups.recv().await.is_some() {
+				match geoip.reload(true) {
+					Ok(_) => info!("Reloaded GeoIP database built at {:?}", geoip.build_epoch()),
+					Err(err) => warn!("Reloading GeoIP database failed: {}", err),
+				}
+			}
+		});
+	}
 
 	HttpServer::new(move || {
 		let cors = Cors::default()
@@ -220,30 +722,75 @@ async fn main() -> Result<(), Box<dyn Error>> {
 			})
 			.allow_any_method()
 			.allowed_header(header::AUTHORIZATION)
-			.allowed_header(header::CONTENT_TYPE);
+			.allowed_header(header::CONTENT_TYPE)
+			.allowed_header(idempotency::KEY_HEADER)
+			.expose_headers([idempotency::REPLAYED_HEADER]);
 
 		App::new()
 			.wrap(cors)
 			.wrap(HostCheckWrap(conf::HOST))
-			.wrap(Logger::default())
+			.wrap(
+				Logger::new(r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+					.custom_request_replace("client_ip", move |req| {
+						req.peer_addr().map_or_else(
+							|| String::from("-"),
+							|peer| {
+								trusted_proxies
+									.resolve(peer.ip(), req.headers())
+									.to_string()
+							},
+						)
+					}),
+			)
 			.app_data(web::Data::new(mongo_client.clone()))
 			.app_data(web::Data::new(db.clone()))
-			.app_data(web::Data::new(geoip_reader))
+			.app_data(web::Data::new(geoip))
+			.app_data(web::Data::new(trusted_proxies))
 			.app_data(web::Data::new(masking_key))
+			.app_data(web::Data::new(content_filter))
+			.app_data(web::Data::new(crisis_detector))
+			.app_data(web::Data::new(storage))
+			.app_data(web::Data::new(link_signer))
+			.app_data(web::Data::new(address_hasher))
+			.app_data(web::Data::new(mailer))
+			.service(services::health::ready)
 			.service(services::schools_list)
+			.service(services::schools::school_stats)
+			.service(services::attachments::upload)
+			.service(services::attachments::get_attachment)
 			.service(services::auth::login)
 			.service(services::auth::logout)
 			.service(services::auth::logout_all)
 			.service(services::auth::register)
 			.service(services::posts::create)
 			.service(services::posts::list)
+			.service(services::posts::search)
 			.service(services::posts::vote)
+			.service(services::posts::vote_poll)
+			.service(services::posts::save_post)
+			.service(services::posts::unsave_post)
+			.service(services::posts::list_saved)
+			.service(services::posts::hide_post)
+			.service(services::posts::list_own_posts)
+			.service(services::posts::list_own_votes)
 			.service(services::profile::update_profile)
+			.service(services::profile::patch_profile)
 			.service(services::profile::get_profile)
 			.service(services::posts::get_single_post)
 			.service(services::profile::get_watched)
 			.service(services::profile::add_watched)
+			.service(services::profile::replace_watched)
 			.service(services::profile::delete_watched)
+			.service(services::profile::get_muted)
+			.service(services::profile::add_muted)
+			.service(services::profile::delete_muted)
+			.service(services::verification::request_verification)
+			.service(services::verification::confirm_verification)
+			.service(services::exports::request_export)
+			.service(services::exports::download_export)
+			.service(services::schools::create_school)
+			.service(services::schools::patch_school)
+			.service(services::schools::retire_school)
 	})
 	.bind(("0.0.0.0", 3000))?
 	.run()
@@ -9,6 +9,7 @@ use aes::cipher::{
 	KeyInit,
 };
 use aes::Aes128;
+use base64::display::Base64Display;
 use mongodb::bson::oid::ObjectId;
 use serde::{
 	Deserialize,
@@ -24,6 +25,13 @@ pub struct MaskedObjectId(#[serde(with = "crate::base64_serde")] [u8; 16]);
 #[derive(Clone, Deserialize, Serialize)]
 pub struct MaskedSequentialId(#[serde(with = "crate::base64_serde")] [u8; 16]);
 
+// the same encoding as the serialized form
+impl fmt::Display for MaskedObjectId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		Base64Display::with_config(&self.0, base64::URL_SAFE_NO_PAD).fmt(f)
+	}
+}
+
 pub struct MaskingKey(Aes128);
 
 #[derive(Clone, Copy, Debug)]
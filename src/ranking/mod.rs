@@ -0,0 +1,280 @@
+//! Feed ranking algorithms.
+//!
+//! Every algorithm stores its score on each post, so that feeds can be served straight from an index. Scores are recomputed inside the vote transaction; scores that also change with the passage of time are refreshed periodically by [`refresh_rising`].
+
+#[cfg(test)]
+mod tests;
+
+use mongodb::bson::{
+	doc,
+	Bson,
+	DateTime,
+};
+use mongodb::Database;
+
+use crate::conf;
+use crate::types::Vote;
+
+pub trait Ranking: Sync {
+	/// The name of the post field holding the score. Higher scores rank first.
+	fn score_field(&self) -> &'static str;
+
+	/// The score of a post created at the given time with the given votes. Used for newly created posts, which have no votes yet.
+	fn score(&self, votes_up: i32, votes_down: i32, created_at: &DateTime) -> f64;
+
+	/// An aggregation expression evaluating to the post's updated score, matching [`Ranking::score`].
+	///
+	/// Evaluated in the post's update pipeline after `votes_up`, `votes_down` and `absolute_score` have been updated.
+	fn vote_update(&self, change: &VoteChange) -> Bson;
+}
+
+/// How a vote changed a post's score.
+#[derive(Default)]
+pub struct VoteChange {
+	/// The change to `absolute_score`.
+	pub difference: i32,
+	/// The change to the net score of the votes cast within [`conf::RISING_WINDOW`]. A vote being replaced only counts if it was cast within the window.
+	pub windowed_difference: i32,
+}
+
+impl VoteChange {
+	/// The change made by casting `value` in place of the previous vote, given as its value and when it was cast.
+	pub fn new(previous: Option<(i32, DateTime)>, value: i32, now: DateTime) -> Self {
+		let window_start =
+			now.timestamp_millis() - i64::try_from(conf::RISING_WINDOW.as_millis()).unwrap();
+		let (previous_value, previous_windowed) = match previous {
+			Some((previous_value, previous_cast_at)) => (
+				previous_value,
+				if previous_cast_at.timestamp_millis() >= window_start {
+					previous_value
+				} else {
+					0
+				},
+			),
+			None => (0, 0),
+		};
+
+		Self {
+			difference: value - previous_value,
+			windowed_difference: value - previous_windowed,
+		}
+	}
+}
+
+/// Every ranking algorithm, in no particular order.
+pub const ALL: &[&dyn Ranking] = &[&Hot, &Best, &Controversial, &Rising];
+
+/// Gets the time-based offset of the hot score for the given timestamp.
+fn get_trending_score_time(date_time: &DateTime) -> f64 {
+	f64::from(u32::try_from(date_time.timestamp_millis() / 1000 - conf::TRENDING_EPOCH).unwrap())
+		/ conf::TRENDING_DECAY
+}
+
+/// The logarithm of the absolute score, offset by the post's age so that newer posts rank higher.
+pub struct Hot;
+
+impl Ranking for Hot {
+	fn score_field(&self) -> &'static str {
+		"trending_score"
+	}
+
+	fn score(&self, votes_up: i32, votes_down: i32, created_at: &DateTime) -> f64 {
+		let absolute_score = votes_up - votes_down;
+		f64::from(absolute_score.signum()) * f64::from(absolute_score.unsigned_abs() + 1).ln()
+			+ get_trending_score_time(created_at)
+	}
+
+	fn vote_update(&self, _change: &VoteChange) -> Bson {
+		Bson::from(doc! {
+			"$add": [
+				{"$multiply": [
+					{"$cond": [
+						{"$lt": ["$absolute_score", 0]},
+						-1,
+						1,
+					]},
+					{"$ln":
+						{"$add": [1, {"$abs": "$absolute_score"}]}},
+				]},
+				// the same as `get_trending_score_time`, given the post's creation time from its `_id`
+				{"$divide": [
+					{"$subtract": [
+						{"$divide": [{"$toLong": {"$toDate": "$_id"}}, 1000]},
+						{"$literal": conf::TRENDING_EPOCH},
+					]},
+					{"$literal": conf::TRENDING_DECAY},
+				]},
+			],
+		})
+	}
+}
+
+/// The lower bound of the Wilson score confidence interval for the proportion of upvotes.
+pub struct Best;
+
+impl Ranking for Best {
+	fn score_field(&self) -> &'static str {
+		"best_score"
+	}
+
+	fn score(&self, votes_up: i32, votes_down: i32, _created_at: &DateTime) -> f64 {
+		let n = f64::from(votes_up + votes_down);
+		if n == 0.0 {
+			return 0.0;
+		}
+
+		let z = conf::BEST_CONFIDENCE_Z;
+		let p = f64::from(votes_up) / n;
+		(p + z * z / 2.0 / n - z * ((p * (1.0 - p) + z * z / 4.0 / n) / n).sqrt())
+			/ (1.0 + z * z / n)
+	}
+
+	fn vote_update(&self, _change: &VoteChange) -> Bson {
+		let z = conf::BEST_CONFIDENCE_Z;
+
+		Bson::from(doc! {
+			"$let": {
+				"vars": {
+					"n": {"$add": ["$votes_up", "$votes_down"]},
+				},
+				"in": {"$cond": [
+					{"$eq": ["$$n", 0]},
+					0.0,
+					{"$let": {
+						"vars": {
+							"p": {"$divide": ["$votes_up", "$$n"]},
+						},
+						"in": {"$divide": [
+							{"$subtract": [
+								{"$add": ["$$p", {"$divide": [{"$literal": z * z / 2.0}, "$$n"]}]},
+								{"$multiply": [
+									{"$literal": z},
+									{"$sqrt": {"$divide": [
+										{"$add": [
+											{"$multiply": ["$$p", {"$subtract": [1, "$$p"]}]},
+											{"$divide": [{"$literal": z * z / 4.0}, "$$n"]},
+										]},
+										"$$n",
+									]}},
+								]},
+							]},
+							{"$add": [1, {"$divide": [{"$literal": z * z}, "$$n"]}]},
+						]},
+					}},
+				]},
+			},
+		})
+	}
+}
+
+/// The total number of votes, raised to the power of the ratio between the minority and majority vote. Posts with many votes evenly split between up and down rank highest.
+pub struct Controversial;
+
+impl Ranking for Controversial {
+	fn score_field(&self) -> &'static str {
+		"controversial_score"
+	}
+
+	fn score(&self, votes_up: i32, votes_down: i32, _created_at: &DateTime) -> f64 {
+		if votes_up <= 0 || votes_down <= 0 {
+			return 0.0;
+		}
+
+		f64::from(votes_up + votes_down)
+			.powf(f64::from(votes_up.min(votes_down)) / f64::from(votes_up.max(votes_down)))
+	}
+
+	fn vote_update(&self, _change: &VoteChange) -> Bson {
+		Bson::from(doc! {
+			"$cond": [
+				{"$or": [
+					{"$lte": ["$votes_up", 0]},
+					{"$lte": ["$votes_down", 0]},
+				]},
+				0.0,
+				{"$pow": [
+					{"$add": ["$votes_up", "$votes_down"]},
+					{"$divide": [
+						{"$min": ["$votes_up", "$votes_down"]},
+						{"$max": ["$votes_up", "$votes_down"]},
+					]},
+				]},
+			],
+		})
+	}
+}
+
+/// The net score gained per hour over the last [`conf::RISING_WINDOW`].
+///
+/// Votes add to the score as they're cast, and [`refresh_rising`] drops votes that have fallen out of the window. Until the next refresh, scores still include votes that left the window since the last one.
+pub struct Rising;
+
+impl Rising {
+	fn window_hours() -> f64 {
+		conf::RISING_WINDOW.as_secs_f64() / 3600.0
+	}
+}
+
+impl Ranking for Rising {
+	fn score_field(&self) -> &'static str {
+		"rising_score"
+	}
+
+	/// Assumes that every vote was cast within the window.
+	fn score(&self, votes_up: i32, votes_down: i32, _created_at: &DateTime) -> f64 {
+		f64::from(votes_up - votes_down) / Self::window_hours()
+	}
+
+	fn vote_update(&self, change: &VoteChange) -> Bson {
+		Bson::from(doc! {
+			"$add": [
+				{"$ifNull": ["$rising_score", 0.0]},
+				{"$literal": f64::from(change.windowed_difference) / Self::window_hours()},
+			],
+		})
+	}
+}
+
+/// Recomputes the rising score of every post from the votes cast within the window.
+///
+/// The scores are merged into the posts by a single aggregation, so that feeds never see them partially reset. Votes cast while the refresh is running may be missed until the next refresh.
+pub async fn refresh_rising(db: &Database) -> mongodb::error::Result<()> {
+	let field = Rising.score_field();
+	let cutoff = DateTime::from_millis(
+		DateTime::now().timestamp_millis()
+			- i64::try_from(conf::RISING_WINDOW.as_millis()).unwrap(),
+	);
+
+	db.collection::<Vote>("votes")
+		.aggregate(
+			[
+				doc! {"$match": {"updated_at": {"$gte": cutoff}}},
+				doc! {"$group": {
+					"_id": "$post",
+					"score": {"$sum": {"$divide": ["$value", {"$literal": Rising::window_hours()}]}},
+				}},
+				// posts whose votes have all left the window
+				doc! {"$unionWith": {
+					"coll": "posts",
+					"pipeline": [
+						{"$match": {field: {"$ne": 0.0}}},
+						{"$project": {"score": {"$literal": 0.0}}},
+					],
+				}},
+				doc! {"$group": {
+					"_id": "$_id",
+					"score": {"$sum": "$score"},
+				}},
+				doc! {"$project": {field: "$score"}},
+				doc! {"$merge": {
+					"into": "posts",
+					"whenMatched": "merge",
+					"whenNotMatched": "discard",
+				}},
+			],
+			None,
+		)
+		.await?;
+
+	Ok(())
+}
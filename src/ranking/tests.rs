@@ -0,0 +1,92 @@
+use mongodb::bson::DateTime;
+
+use super::{
+	Best,
+	Controversial,
+	Hot,
+	Ranking,
+	VoteChange,
+};
+use crate::conf;
+
+fn assert_close(actual: f64, expected: f64) {
+	assert!(
+		(actual - expected).abs() < 1e-9,
+		"expected {}, got {}",
+		expected,
+		actual
+	);
+}
+
+#[test]
+fn test_hot() {
+	let epoch = DateTime::from_millis(conf::TRENDING_EPOCH * 1000);
+	assert_close(Hot.score(0, 0, &epoch), 0.0);
+	assert_close(Hot.score(9, 0, &epoch), 10_f64.ln());
+	assert_close(Hot.score(3, 12, &epoch), -10_f64.ln());
+	assert_close(Hot.score(5, 5, &epoch), 0.0);
+
+	let later = DateTime::from_millis((conf::TRENDING_EPOCH + 86400) * 1000);
+	assert_close(Hot.score(0, 0, &later), 86400.0 / conf::TRENDING_DECAY);
+	assert_close(
+		Hot.score(9, 0, &later) - Hot.score(9, 0, &epoch),
+		86400.0 / conf::TRENDING_DECAY,
+	);
+	assert!(Hot.score(0, 0, &later) > Hot.score(1, 0, &epoch));
+	assert!(Hot.score(0, 0, &later) < Hot.score(9, 0, &epoch));
+}
+
+#[test]
+fn test_best() {
+	let now = DateTime::now();
+	assert_close(Best.score(0, 0, &now), 0.0);
+	assert_close(Best.score(0, 7, &now), 0.0);
+	assert_close(
+		Best.score(1, 0, &now),
+		1.0 / (1.0 + conf::BEST_CONFIDENCE_Z.powi(2)),
+	);
+
+	// more votes in the same proportion give more confidence
+	assert!(Best.score(90, 10, &now) > Best.score(9, 1, &now));
+	assert!(Best.score(9, 1, &now) > Best.score(1, 0, &now));
+	assert!(Best.score(90, 10, &now) < 0.9);
+	assert!(Best.score(1000, 0, &now) < 1.0);
+}
+
+#[test]
+fn test_controversial() {
+	let now = DateTime::now();
+	assert_close(Controversial.score(0, 0, &now), 0.0);
+	assert_close(Controversial.score(12, 0, &now), 0.0);
+	assert_close(Controversial.score(0, 12, &now), 0.0);
+	assert_close(Controversial.score(5, 5, &now), 10.0);
+	assert_close(Controversial.score(8, 2, &now), 10_f64.powf(0.25));
+	assert_close(
+		Controversial.score(2, 8, &now),
+		Controversial.score(8, 2, &now),
+	);
+
+	assert!(Controversial.score(50, 50, &now) > Controversial.score(5, 5, &now));
+	assert!(Controversial.score(5, 5, &now) > Controversial.score(90, 10, &now));
+}
+
+#[test]
+fn test_vote_change() {
+	let now = DateTime::now();
+	let window = i64::try_from(conf::RISING_WINDOW.as_millis()).unwrap();
+	let recently = DateTime::from_millis(now.timestamp_millis() - window + 1000);
+	let long_ago = DateTime::from_millis(now.timestamp_millis() - window - 1000);
+
+	let change = VoteChange::new(None, 1, now);
+	assert_eq!((change.difference, change.windowed_difference), (1, 1));
+
+	let change = VoteChange::new(Some((-1, recently)), 1, now);
+	assert_eq!((change.difference, change.windowed_difference), (2, 2));
+
+	// the replaced vote no longer counts towards the window
+	let change = VoteChange::new(Some((-1, long_ago)), 1, now);
+	assert_eq!((change.difference, change.windowed_difference), (2, 1));
+
+	let change = VoteChange::new(Some((1, long_ago)), 0, now);
+	assert_eq!((change.difference, change.windowed_difference), (-1, 0));
+}
@@ -0,0 +1,178 @@
+//! Activity statistics for each school.
+//!
+//! The counts are rolled up from posts periodically by [`refresh`] rather than on every request. Before they're published, small counts are withheld and the rest are rounded, so that the statistics can't be used to tell what a handful of users did.
+
+#[cfg(test)]
+mod tests;
+
+use std::time::Duration;
+
+use mongodb::bson::{
+	doc,
+	DateTime,
+	Document,
+};
+use mongodb::Database;
+use serde::Serialize;
+
+use crate::conf;
+use crate::types::{
+	FacultyPostCount,
+	PostStatus,
+	SchoolStats,
+};
+
+/// Withholds a count below [`conf::STATS_MIN_COUNT`], and rounds it down to a multiple of [`conf::STATS_ROUNDING`] otherwise.
+pub fn obscure(count: i64) -> Option<i64> {
+	(count >= conf::STATS_MIN_COUNT).then(|| count - count % conf::STATS_ROUNDING)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct PostCounts {
+	pub last_day: Option<i64>,
+	pub last_week: Option<i64>,
+	pub all_time: Option<i64>,
+}
+
+/// School statistics as they're shown to users. Withheld counts are `None`.
+#[derive(Serialize)]
+pub struct PublishedStats {
+	pub posts: PostCounts,
+	/// The number of users who posted in the last week.
+	pub active_posters: Option<i64>,
+	/// Upvotes and downvotes on the school's posts.
+	pub votes_cast: Option<i64>,
+	/// The faculties with the most posts, most first. Faculties whose counts are withheld aren't listed.
+	pub top_faculties: Vec<FacultyPostCount>,
+	/// When the statistics were computed, if ever.
+	pub updated_at: Option<String>,
+}
+
+impl PublishedStats {
+	/// The statistics of a school that has no visible posts yet.
+	pub fn empty() -> Self {
+		Self {
+			posts: PostCounts {
+				last_day: None,
+				last_week: None,
+				all_time: None,
+			},
+			active_posters: None,
+			votes_cast: None,
+			top_faculties: Vec::new(),
+			updated_at: None,
+		}
+	}
+}
+
+impl TryFrom<&SchoolStats> for PublishedStats {
+	type Error = mongodb::bson::datetime::Error;
+
+	fn try_from(stats: &SchoolStats) -> Result<Self, Self::Error> {
+		let mut faculties = stats
+			.faculties
+			.iter()
+			.filter(|count| obscure(count.posts).is_some())
+			.collect::<Vec<&FacultyPostCount>>();
+		// sorting by the exact counts would give away which of two faculties with the same rounded count has more posts
+		faculties.sort_by_cached_key(|count| {
+			(
+				std::cmp::Reverse(obscure(count.posts)),
+				format!("{:?}", count.faculty),
+			)
+		});
+
+		Ok(Self {
+			posts: PostCounts {
+				last_day: obscure(stats.posts_day),
+				last_week: obscure(stats.posts_week),
+				all_time: obscure(stats.posts_all_time),
+			},
+			active_posters: obscure(stats.active_posters),
+			votes_cast: obscure(stats.votes_cast),
+			top_faculties: faculties
+				.into_iter()
+				.take(conf::STATS_TOP_FACULTIES)
+				.map(|count| FacultyPostCount {
+					faculty: count.faculty.clone(),
+					posts: obscure(count.posts).unwrap(),
+				})
+				.collect(),
+			updated_at: Some(stats.refreshed_at.try_to_rfc3339_string()?),
+		})
+	}
+}
+
+fn ago(duration: Duration) -> DateTime {
+	DateTime::from_millis(
+		DateTime::now().timestamp_millis() - i64::try_from(duration.as_millis()).unwrap(),
+	)
+}
+
+/// Whether a post was created after a time, as an aggregation expression.
+fn created_since(time: DateTime) -> Document {
+	doc! {"$gte": [{"$toDate": "$_id"}, time]}
+}
+
+/// Recomputes the statistics of every school with visible posts, and drops those of schools that no longer have any.
+pub async fn refresh(db: &Database) -> mongodb::error::Result<()> {
+	let refreshed_at = DateTime::now();
+	let day_ago = ago(Duration::from_secs(3600 * 24));
+	let week_ago = ago(Duration::from_secs(3600 * 24 * 7));
+
+	db.collection::<Document>("posts")
+		.aggregate(
+			[
+				doc! {"$match": {"status": PostStatus::Visible}},
+				doc! {"$group": {
+					"_id": {"school_id": "$school_id", "faculty": "$faculty"},
+					"posts_day": {"$sum": {"$cond": [created_since(day_ago), 1, 0]}},
+					"posts_week": {"$sum": {"$cond": [created_since(week_ago), 1, 0]}},
+					"posts_all_time": {"$sum": 1},
+					"posters": {"$addToSet": {"$cond": [created_since(week_ago), "$owner", null]}},
+					"votes_cast": {"$sum": {"$add": ["$votes_up", "$votes_down"]}},
+				}},
+				doc! {"$group": {
+					"_id": "$_id.school_id",
+					"posts_day": {"$sum": "$posts_day"},
+					"posts_week": {"$sum": "$posts_week"},
+					"posts_all_time": {"$sum": "$posts_all_time"},
+					"posters": {"$push": "$posters"},
+					"votes_cast": {"$sum": "$votes_cast"},
+					"faculties": {"$push": {"faculty": "$_id.faculty", "posts": "$posts_all_time"}},
+				}},
+				doc! {"$project": {
+					"posts_day": {"$toLong": "$posts_day"},
+					"posts_week": {"$toLong": "$posts_week"},
+					"posts_all_time": {"$toLong": "$posts_all_time"},
+					"active_posters": {"$toLong": {"$size": {"$setDifference": [
+						{"$reduce": {
+							"input": "$posters",
+							"initialValue": [],
+							"in": {"$setUnion": ["$$value", "$$this"]},
+						}},
+						[null],
+					]}}},
+					"votes_cast": {"$toLong": "$votes_cast"},
+					"faculties": {"$filter": {
+						"input": "$faculties",
+						"cond": {"$ne": [{"$ifNull": ["$$this.faculty", null]}, null]},
+					}},
+					"refreshed_at": {"$literal": refreshed_at},
+				}},
+				doc! {"$merge": {
+					"into": "school_stats",
+					"whenMatched": "replace",
+					"whenNotMatched": "insert",
+				}},
+			],
+			None,
+		)
+		.await?;
+
+	db.collection::<SchoolStats>("school_stats")
+		.delete_many(doc! {"refreshed_at": {"$lt": refreshed_at}}, None)
+		.await?;
+
+	Ok(())
+}
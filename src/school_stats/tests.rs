@@ -0,0 +1,86 @@
+use mongodb::bson::DateTime;
+
+use super::{
+	obscure,
+	PostCounts,
+	PublishedStats,
+};
+use crate::types::{
+	FacultyPostCount,
+	PosterFaculty,
+	SchoolStats,
+};
+
+#[test]
+fn test_obscure() {
+	assert_eq!(obscure(0), None);
+	assert_eq!(obscure(9), None);
+	assert_eq!(obscure(10), Some(10));
+	assert_eq!(obscure(14), Some(10));
+	assert_eq!(obscure(15), Some(15));
+	assert_eq!(obscure(1234), Some(1230));
+}
+
+#[test]
+fn test_publish() {
+	let refreshed_at = DateTime::from_millis(1_700_000_000_000);
+	let stats = SchoolStats {
+		school_id: String::from("UVIC"),
+		posts_day: 3,
+		posts_week: 27,
+		posts_all_time: 512,
+		active_posters: 9,
+		votes_cast: 4021,
+		faculties: vec![
+			FacultyPostCount {
+				faculty: PosterFaculty::Arts,
+				posts: 40,
+			},
+			FacultyPostCount {
+				faculty: PosterFaculty::Medicine,
+				posts: 4,
+			},
+			FacultyPostCount {
+				faculty: PosterFaculty::Engineering,
+				posts: 101,
+			},
+			FacultyPostCount {
+				faculty: PosterFaculty::Business,
+				posts: 42,
+			},
+			FacultyPostCount {
+				faculty: PosterFaculty::Education,
+				posts: 12,
+			},
+		],
+		refreshed_at,
+	};
+
+	let published = PublishedStats::try_from(&stats).unwrap();
+	assert_eq!(
+		published.posts,
+		PostCounts {
+			last_day: None,
+			last_week: Some(25),
+			all_time: Some(510),
+		}
+	);
+	assert_eq!(published.active_posters, None);
+	assert_eq!(published.votes_cast, Some(4020));
+	assert_eq!(
+		published
+			.top_faculties
+			.iter()
+			.map(|count| (format!("{:?}", count.faculty), count.posts))
+			.collect::<Vec<(String, i64)>>(),
+		vec![
+			(String::from("Engineering"), 100),
+			(String::from("Arts"), 40),
+			(String::from("Business"), 40),
+		]
+	);
+	assert_eq!(
+		published.updated_at.as_deref(),
+		Some("2023-11-14T22:13:20Z")
+	);
+}
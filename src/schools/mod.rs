@@ -0,0 +1,469 @@
+//! Validates schools, and imports them in bulk from CSV or JSON datasets.
+//!
+//! Datasets list each school's `id`, `name`, `latitude`, `longitude`, `aliases` and `email_domains`. In CSV datasets, aliases and email domains are separated by semicolons. Importing upserts the schools, leaving their counselling contacts, verification requirements and retirement as they are.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{
+	HashMap,
+	HashSet,
+};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use futures::TryStreamExt;
+use mongodb::bson::{
+	doc,
+	Document,
+};
+use mongodb::options::UpdateOptions;
+use mongodb::Database;
+use serde::Deserialize;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::conf;
+use crate::types::School;
+
+/// Checks that a school ID is 1–[`conf::SCHOOL_ID_MAX_LENGTH`] uppercase ASCII letters and digits.
+pub fn is_valid_id(id: &str) -> bool {
+	!id.is_empty()
+		&& id.len() <= conf::SCHOOL_ID_MAX_LENGTH
+		&& id
+			.bytes()
+			.all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+/// Checks that a position is on Earth. `2dsphere` indexes refuse any other.
+pub fn is_valid_position(longitude: f64, latitude: f64) -> bool {
+	(-180.0..=180.0).contains(&longitude) && (-90.0..=90.0).contains(&latitude)
+}
+
+/// Trims a school name or alias, or returns `None` if it's empty or longer than [`conf::SCHOOL_NAME_MAX_LENGTH`].
+pub fn normalize_name(name: &str) -> Option<&str> {
+	let name = name.trim();
+	(!name.is_empty() && name.graphemes(true).count() <= conf::SCHOOL_NAME_MAX_LENGTH)
+		.then_some(name)
+}
+
+/// Lowercases text and strips its accents, separating words with single spaces, so that it can be compared case- and accent-insensitively.
+pub fn fold(text: &str) -> String {
+	text.nfkd()
+		.filter(|&c| !is_combining_mark(c))
+		.collect::<String>()
+		.to_lowercase()
+		.split_whitespace()
+		.collect::<Vec<&str>>()
+		.join(" ")
+}
+
+/// Lists what searches for a school match the start of: its folded ID, name and aliases, starting from each of their words.
+pub fn search_keys(id: &str, name: &str, aliases: &[String]) -> Vec<String> {
+	let mut keys = Vec::<String>::new();
+
+	for text in [id, name]
+		.into_iter()
+		.chain(aliases.iter().map(String::as_str))
+	{
+		let folded = fold(text);
+		let word_starts = folded
+			.char_indices()
+			.filter(|&(i, c)| {
+				c.is_alphanumeric() && (i == 0 || !folded[..i].ends_with(char::is_alphanumeric))
+			})
+			.map(|(i, _)| i);
+
+		for start in word_starts {
+			let key = &folded[start..];
+			if !keys.iter().any(|k| k == key) {
+				keys.push(String::from(key));
+			}
+		}
+	}
+
+	keys
+}
+
+/// Lowercases an email domain, or returns `None` if it isn't a domain with at least two labels.
+pub fn normalize_email_domain(domain: &str) -> Option<String> {
+	let domain = domain.trim().trim_start_matches('@').to_ascii_lowercase();
+	let labels = domain.split('.').collect::<Vec<&str>>();
+
+	(labels.len() >= 2
+		&& domain.len() <= 253
+		&& labels.iter().all(|label| {
+			(1..=63).contains(&label.len())
+				&& !label.starts_with('-')
+				&& !label.ends_with('-')
+				&& label
+					.bytes()
+					.all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+		}))
+	.then_some(domain)
+}
+
+/// A school in a dataset.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SchoolRecord {
+	pub id: String,
+	pub name: String,
+	pub latitude: f64,
+	pub longitude: f64,
+	#[serde(default)]
+	pub aliases: Vec<String>,
+	#[serde(default)]
+	pub email_domains: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct CsvRecord {
+	id: String,
+	name: String,
+	latitude: f64,
+	longitude: f64,
+	#[serde(default)]
+	aliases: String,
+	#[serde(default)]
+	email_domains: String,
+}
+
+fn split_list(list: &str) -> Vec<String> {
+	list.split(';')
+		.map(str::trim)
+		.filter(|item| !item.is_empty())
+		.map(String::from)
+		.collect()
+}
+
+impl From<CsvRecord> for SchoolRecord {
+	fn from(record: CsvRecord) -> Self {
+		Self {
+			id: record.id,
+			name: record.name,
+			latitude: record.latitude,
+			longitude: record.longitude,
+			aliases: split_list(&record.aliases),
+			email_domains: split_list(&record.email_domains),
+		}
+	}
+}
+
+/// A record of a dataset was invalid.
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidRecord {
+	/// The position of the record in the dataset, starting from 1.
+	pub number: usize,
+	pub reason: &'static str,
+}
+
+impl fmt::Display for InvalidRecord {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "record {}: {}", self.number, self.reason)
+	}
+}
+
+impl Error for InvalidRecord {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	Csv,
+	Json,
+}
+
+impl Format {
+	pub fn from_path(path: &Path) -> Option<Self> {
+		match path.extension()?.to_str()? {
+			"csv" => Some(Self::Csv),
+			"json" => Some(Self::Json),
+			_ => None,
+		}
+	}
+}
+
+/// Reads and validates a dataset.
+pub fn parse<R: Read>(format: Format, reader: R) -> Result<Vec<SchoolRecord>, Box<dyn Error>> {
+	let records = match format {
+		Format::Csv => csv::ReaderBuilder::new()
+			.trim(csv::Trim::All)
+			.from_reader(reader)
+			.deserialize::<CsvRecord>()
+			.map(|record| record.map(SchoolRecord::from))
+			.collect::<Result<Vec<SchoolRecord>, csv::Error>>()?,
+		Format::Json => serde_json::from_reader(reader)?,
+	};
+
+	Ok(validate(records)?)
+}
+
+/// Reads and validates a dataset from a `.csv` or `.json` file.
+pub fn load(path: &str) -> Result<Vec<SchoolRecord>, Box<dyn Error>> {
+	let format = Format::from_path(Path::new(path)).ok_or("expected a .csv or .json file")?;
+	parse(format, File::open(path)?)
+}
+
+/// Normalizes records, and checks that they're valid and have distinct IDs.
+fn validate(records: Vec<SchoolRecord>) -> Result<Vec<SchoolRecord>, InvalidRecord> {
+	let mut ids = HashSet::new();
+
+	records
+		.into_iter()
+		.enumerate()
+		.map(|(i, record)| {
+			let invalid = |reason| InvalidRecord {
+				number: i + 1,
+				reason,
+			};
+
+			if !is_valid_id(&record.id) {
+				return Err(invalid("invalid id"));
+			}
+
+			if !ids.insert(record.id.clone()) {
+				return Err(invalid("duplicate id"));
+			}
+
+			if !is_valid_position(record.longitude, record.latitude) {
+				return Err(invalid("position out of range"));
+			}
+
+			let name = normalize_name(&record.name).ok_or_else(|| invalid("invalid name"))?;
+
+			let mut aliases = Vec::<String>::new();
+			for alias in &record.aliases {
+				let alias = normalize_name(alias).ok_or_else(|| invalid("invalid alias"))?;
+				if alias != name && !aliases.iter().any(|a| a == alias) {
+					aliases.push(String::from(alias));
+				}
+			}
+
+			let mut email_domains = Vec::<String>::new();
+			for domain in &record.email_domains {
+				let domain = normalize_email_domain(domain)
+					.ok_or_else(|| invalid("invalid email domain"))?;
+				if !email_domains.contains(&domain) {
+					email_domains.push(domain);
+				}
+			}
+
+			Ok(SchoolRecord {
+				id: record.id,
+				name: String::from(name),
+				latitude: record.latitude,
+				longitude: record.longitude,
+				aliases,
+				email_domains,
+			})
+		})
+		.collect()
+}
+
+/// A field of a school that an import changes.
+#[derive(Debug, PartialEq)]
+pub struct Change {
+	pub field: &'static str,
+	pub from: String,
+	pub to: String,
+}
+
+/// Lists the fields a record changes in a stored school.
+pub fn diff(school: &School, record: &SchoolRecord) -> Vec<Change> {
+	let mut changes = Vec::new();
+
+	if school.name != record.name {
+		changes.push(Change {
+			field: "name",
+			from: school.name.clone(),
+			to: record.name.clone(),
+		});
+	}
+
+	let [longitude, latitude] = school.position.coordinates;
+	if longitude != record.longitude || latitude != record.latitude {
+		changes.push(Change {
+			field: "position",
+			from: format!("{}, {}", latitude, longitude),
+			to: format!("{}, {}", record.latitude, record.longitude),
+		});
+	}
+
+	if school.aliases != record.aliases {
+		changes.push(Change {
+			field: "aliases",
+			from: school.aliases.join("; "),
+			to: record.aliases.join("; "),
+		});
+	}
+
+	if school.email_domains != record.email_domains {
+		changes.push(Change {
+			field: "email_domains",
+			from: school.email_domains.join("; "),
+			to: record.email_domains.join("; "),
+		});
+	}
+
+	changes
+}
+
+/// The differences an import made, by school ID.
+#[derive(Debug, Default, PartialEq)]
+pub struct ImportReport {
+	pub added: Vec<String>,
+	pub changed: Vec<(String, Vec<Change>)>,
+	pub unchanged: Vec<String>,
+}
+
+impl ImportReport {
+	/// Compares records with the stored schools that have the same IDs.
+	pub fn compare(existing: &HashMap<String, School>, records: &[SchoolRecord]) -> Self {
+		let mut report = Self::default();
+
+		for record in records {
+			match existing.get(&record.id) {
+				None => report.added.push(record.id.clone()),
+				Some(school) => {
+					let changes = diff(school, record);
+					if changes.is_empty() {
+						report.unchanged.push(record.id.clone());
+					} else {
+						report.changed.push((record.id.clone(), changes));
+					}
+				}
+			}
+		}
+
+		report
+	}
+}
+
+impl fmt::Display for ImportReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for id in &self.added {
+			writeln!(f, "+ {}", id)?;
+		}
+
+		for (id, changes) in &self.changed {
+			for change in changes {
+				writeln!(
+					f,
+					"~ {} {}: {:?} -> {:?}",
+					id, change.field, change.from, change.to
+				)?;
+			}
+		}
+
+		write!(
+			f,
+			"{} added, {} changed, {} unchanged",
+			self.added.len(),
+			self.changed.len(),
+			self.unchanged.len()
+		)
+	}
+}
+
+/// Upserts validated records into `schools`, skipping the unchanged ones.
+pub async fn import(
+	db: &Database,
+	records: &[SchoolRecord],
+) -> mongodb::error::Result<ImportReport> {
+	let schools = db.collection::<School>("schools");
+
+	let existing = schools
+		.find(
+			doc! {"_id": {"$in": records.iter().map(|record| &record.id).collect::<Vec<&String>>()}},
+			None,
+		)
+		.await?
+		.map_ok(|school| (school.id.clone(), school))
+		.try_collect::<HashMap<String, School>>()
+		.await?;
+
+	let report = ImportReport::compare(&existing, records);
+	let unchanged = report.unchanged.iter().collect::<HashSet<&String>>();
+
+	for record in records {
+		if unchanged.contains(&record.id) {
+			continue;
+		}
+
+		schools
+			.update_one(
+				doc! {"_id": {"$eq": &record.id}},
+				doc! {
+					"$set": {
+						"name": &record.name,
+						"position": {
+							"type": "Point",
+							"coordinates": [record.longitude, record.latitude],
+						},
+						"aliases": &record.aliases,
+						"email_domains": &record.email_domains,
+						"search_keys": search_keys(&record.id, &record.name, &record.aliases),
+					},
+				},
+				UpdateOptions::builder().upsert(true).build(),
+			)
+			.await?;
+	}
+
+	Ok(report)
+}
+
+/// Sets the search keys of schools stored before they were searchable.
+pub async fn backfill_search_keys(db: &Database) -> mongodb::error::Result<()> {
+	let schools = db.collection::<School>("schools");
+	let mut missing = schools
+		.find(doc! {"search_keys": {"$exists": false}}, None)
+		.await?;
+
+	while let Some(school) = missing.try_next().await? {
+		schools
+			.update_one(
+				doc! {"_id": {"$eq": &school.id}},
+				doc! {"$set": {"search_keys": search_keys(&school.id, &school.name, &school.aliases)}},
+				None,
+			)
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// Filters for a school and its campuses.
+pub fn and_campuses(school_id: &str) -> Document {
+	doc! {
+		"$or": [
+			{"_id": {"$eq": school_id}},
+			{"parent_id": {"$eq": school_id}},
+		],
+	}
+}
+
+/// Adds the IDs of the campuses of the given schools, so that scoping to a school includes its campuses.
+pub async fn with_campuses(
+	db: &Database,
+	mut school_ids: Vec<String>,
+) -> mongodb::error::Result<Vec<String>> {
+	let campus_ids = db
+		.collection::<School>("schools")
+		.find(doc! {"parent_id": {"$in": &school_ids}}, None)
+		.await?
+		.map_ok(|campus| campus.id)
+		.try_collect::<Vec<String>>()
+		.await?;
+
+	for campus_id in campus_ids {
+		if !school_ids.contains(&campus_id) {
+			school_ids.push(campus_id);
+		}
+	}
+
+	Ok(school_ids)
+}
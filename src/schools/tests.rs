@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use mongodb::bson::doc;
+
+use super::{
+	and_campuses,
+	fold,
+	is_valid_id,
+	parse,
+	search_keys,
+	Change,
+	Format,
+	ImportReport,
+	InvalidRecord,
+	SchoolRecord,
+};
+use crate::types::{
+	Point,
+	School,
+};
+
+fn uvic() -> SchoolRecord {
+	SchoolRecord {
+		id: String::from("UVIC"),
+		name: String::from("University of Victoria"),
+		latitude: 48.4633,
+		longitude: -123.3117,
+		aliases: vec![String::from("UVic")],
+		email_domains: vec![String::from("uvic.ca")],
+	}
+}
+
+fn invalid(input: &str) -> InvalidRecord {
+	*parse(Format::Json, input.as_bytes())
+		.unwrap_err()
+		.downcast::<InvalidRecord>()
+		.unwrap()
+}
+
+#[test]
+fn test_valid_id() {
+	assert!(is_valid_id("UVIC"));
+	assert!(is_valid_id("U2"));
+	assert!(!is_valid_id(""));
+	assert!(!is_valid_id("uvic"));
+	assert!(!is_valid_id("UVIC-2"));
+	assert!(!is_valid_id("ABCDEFGHIJKLMNOPQ"));
+}
+
+#[test]
+fn test_fold() {
+	assert_eq!(fold("Université  de\tMONTRÉAL"), "universite de montreal");
+	assert_eq!(fold("École Polytechnique"), "ecole polytechnique");
+}
+
+#[test]
+fn test_search_keys() {
+	assert_eq!(
+		search_keys(
+			"UDEM",
+			"Université de Montréal",
+			&[String::from("UdeM"), String::from("U of M")]
+		),
+		vec![
+			"udem",
+			"universite de montreal",
+			"de montreal",
+			"montreal",
+			"u of m",
+			"of m",
+			"m",
+		]
+	);
+}
+
+#[test]
+fn test_parse_csv() {
+	let csv = "\
+id,name,latitude,longitude,aliases,email_domains
+UVIC, University of Victoria ,48.4633,-123.3117,UVic; University of Victoria,@UVic.ca;uvic.ca
+UBC,University of British Columbia,49.2606,-123.2460,,
+";
+	let records = parse(Format::Csv, csv.as_bytes()).unwrap();
+	assert_eq!(records.len(), 2);
+	assert_eq!(records[0], uvic());
+	assert_eq!(records[1].aliases, Vec::<String>::new());
+	assert_eq!(records[1].email_domains, Vec::<String>::new());
+}
+
+#[test]
+fn test_parse_json() {
+	let json = r#"[
+		{"id": "UVIC", "name": "University of Victoria", "latitude": 48.4633, "longitude": -123.3117, "aliases": ["UVic"], "email_domains": ["uvic.ca"]}
+	]"#;
+	assert_eq!(parse(Format::Json, json.as_bytes()).unwrap(), vec![uvic()]);
+	assert!(parse(Format::Json, r#"[{"id": "UVIC"}]"#.as_bytes()).is_err());
+}
+
+#[test]
+fn test_invalid_records() {
+	assert_eq!(
+		invalid(r#"[{"id": "uvic", "name": "a", "latitude": 0, "longitude": 0}]"#),
+		InvalidRecord {
+			number: 1,
+			reason: "invalid id",
+		}
+	);
+	assert_eq!(
+		invalid(
+			r#"[
+				{"id": "A", "name": "a", "latitude": 0, "longitude": 0},
+				{"id": "A", "name": "b", "latitude": 0, "longitude": 0}
+			]"#
+		),
+		InvalidRecord {
+			number: 2,
+			reason: "duplicate id",
+		}
+	);
+	assert_eq!(
+		invalid(r#"[{"id": "A", "name": "a", "latitude": 91, "longitude": 0}]"#).reason,
+		"position out of range"
+	);
+	assert_eq!(
+		invalid(r#"[{"id": "A", "name": "a", "latitude": 0, "longitude": -180.5}]"#).reason,
+		"position out of range"
+	);
+	assert_eq!(
+		invalid(r#"[{"id": "A", "name": " ", "latitude": 0, "longitude": 0}]"#).reason,
+		"invalid name"
+	);
+	assert_eq!(
+		invalid(
+			r#"[{"id": "A", "name": "a", "latitude": 0, "longitude": 0, "email_domains": ["localhost"]}]"#
+		)
+		.reason,
+		"invalid email domain"
+	);
+	assert_eq!(
+		invalid(
+			r#"[{"id": "A", "name": "a", "latitude": 0, "longitude": 0, "email_domains": ["a b.ca"]}]"#
+		)
+		.reason,
+		"invalid email domain"
+	);
+}
+
+#[test]
+fn test_compare() {
+	let stored = School {
+		id: String::from("UVIC"),
+		name: String::from("UVic"),
+		position: Point {
+			coordinates: [-123.3117, 48.4633],
+		},
+		parent_id: None,
+		aliases: vec![],
+		email_domains: vec![String::from("uvic.ca")],
+		search_keys: vec![],
+		counselling: None,
+		retired: false,
+		verification_required: false,
+	};
+	let existing = HashMap::from([(String::from("UVIC"), stored.clone())]);
+
+	let ubc = SchoolRecord {
+		id: String::from("UBC"),
+		..uvic()
+	};
+	let report = ImportReport::compare(&existing, &[uvic(), ubc]);
+	assert_eq!(report.added, vec![String::from("UBC")]);
+	assert_eq!(
+		report.changed,
+		vec![(
+			String::from("UVIC"),
+			vec![
+				Change {
+					field: "name",
+					from: String::from("UVic"),
+					to: String::from("University of Victoria"),
+				},
+				Change {
+					field: "aliases",
+					from: String::new(),
+					to: String::from("UVic"),
+				},
+			]
+		)]
+	);
+	assert_eq!(
+		report.to_string(),
+		"+ UBC\n~ UVIC name: \"UVic\" -> \"University of Victoria\"\n~ UVIC aliases: \"\" -> \"UVic\"\n1 added, 1 changed, 0 unchanged"
+	);
+
+	let unchanged = SchoolRecord {
+		name: String::from("UVic"),
+		aliases: vec![],
+		..uvic()
+	};
+	assert_eq!(
+		ImportReport::compare(&existing, &[unchanged]).unchanged,
+		vec![String::from("UVIC")]
+	);
+}
+
+#[test]
+fn test_and_campuses() {
+	assert_eq!(
+		and_campuses("UBC"),
+		doc! {
+			"$or": [
+				{"_id": {"$eq": "UBC"}},
+				{"parent_id": {"$eq": "UBC"}},
+			],
+		}
+	);
+}
@@ -0,0 +1,121 @@
+//! Parses full-text search queries written in MongoDB's `$text` syntax, in order to find the matches to highlight in results.
+
+#[cfg(test)]
+mod tests;
+
+use std::ops::Range;
+
+/// The positive parts of a search query: those that can match text.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TextQuery {
+	/// Words, matched case-insensitively at the start of words in the text.
+	pub terms: Vec<String>,
+	/// Exact phrases, matched case-insensitively anywhere in the text.
+	pub phrases: Vec<String>,
+}
+
+impl TextQuery {
+	/// Parses a search query. Quoted phrases and terms prefixed with `-` are supported; negated terms and phrases are dropped, since they never match.
+	pub fn parse(query: &str) -> Self {
+		let mut result = Self::default();
+		let mut rest = query;
+
+		loop {
+			rest = rest.trim_start();
+			let negated = rest.starts_with('-');
+			if negated {
+				rest = &rest[1..];
+			}
+
+			if let Some(quoted) = rest.strip_prefix('"') {
+				let (phrase, after) = quoted.split_once('"').unwrap_or((quoted, ""));
+				if !negated && !phrase.trim().is_empty() {
+					result.phrases.push(phrase.trim().to_owned());
+				}
+				rest = after;
+			} else {
+				let end = rest
+					.find(|c: char| c.is_whitespace() || c == '"')
+					.unwrap_or(rest.len());
+				if end == 0 {
+					if rest.is_empty() {
+						break;
+					}
+					continue;
+				}
+
+				if !negated {
+					result.terms.extend(
+						rest[..end]
+							.split(|c: char| !c.is_alphanumeric())
+							.filter(|term| !term.is_empty())
+							.map(String::from),
+					);
+				}
+				rest = &rest[end..];
+			}
+		}
+
+		result
+	}
+
+	/// Finds the byte ranges of `text` matched by the query, in order and without overlaps.
+	pub fn highlights(&self, text: &str) -> Vec<Range<usize>> {
+		let mut ranges = Vec::new();
+
+		for (start, c) in text.char_indices() {
+			let rest = &text[start..];
+			let at_word_start =
+				c.is_alphanumeric() && !text[..start].ends_with(char::is_alphanumeric);
+
+			if at_word_start
+				&& self
+					.terms
+					.iter()
+					.any(|term| match_length(rest, term).is_some())
+			{
+				let word_length = rest
+					.find(|c: char| !c.is_alphanumeric())
+					.unwrap_or(rest.len());
+				ranges.push(start..start + word_length);
+			}
+
+			for phrase in &self.phrases {
+				if let Some(length) = match_length(rest, phrase) {
+					ranges.push(start..start + length);
+				}
+			}
+		}
+
+		ranges.sort_by_key(|range| range.start);
+
+		let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+		for range in ranges {
+			match merged.last_mut() {
+				Some(last) if range.start <= last.end => {
+					last.end = last.end.max(range.end);
+				}
+				_ => merged.push(range),
+			}
+		}
+		merged
+	}
+}
+
+/// Gets the length in bytes of the prefix of `haystack` that case-insensitively matches `needle`, if any.
+fn match_length(haystack: &str, needle: &str) -> Option<usize> {
+	let mut haystack_chars = haystack.char_indices();
+
+	for n in needle.chars() {
+		let (_, h) = haystack_chars.next()?;
+		if !h.to_lowercase().eq(n.to_lowercase()) {
+			return None;
+		}
+	}
+
+	Some(
+		haystack_chars
+			.next()
+			.map_or(haystack.len(), |(index, _)| index),
+	)
+}
@@ -0,0 +1,48 @@
+use std::ops::Range;
+
+use super::TextQuery;
+
+#[test]
+fn test_parse_terms_and_phrases() {
+	let query = TextQuery::parse(r#"coffee "library basement"  exam"#);
+	assert_eq!(query.terms, ["coffee", "exam"]);
+	assert_eq!(query.phrases, ["library basement"]);
+}
+
+#[test]
+fn test_parse_negation() {
+	let query = TextQuery::parse(r#"coffee -tea -"green tea" -"#);
+	assert_eq!(query.terms, ["coffee"]);
+	assert!(query.phrases.is_empty());
+}
+
+#[test]
+fn test_parse_unterminated_phrase() {
+	let query = TextQuery::parse(r#"midterm "so much"#);
+	assert_eq!(query.terms, ["midterm"]);
+	assert_eq!(query.phrases, ["so much"]);
+}
+
+#[test]
+fn test_highlight_word_prefixes() {
+	let query = TextQuery::parse("run");
+	assert_eq!(
+		query.highlights("Running late, I run. Outrun."),
+		[0..7, 16..19]
+	);
+}
+
+#[test]
+fn test_highlight_merges_overlaps() {
+	let query = TextQuery::parse(r#"library "the library""#);
+	assert_eq!(
+		query.highlights("In the Library."),
+		[Range { start: 3, end: 14 }]
+	);
+}
+
+#[test]
+fn test_highlight_offsets_are_bytes() {
+	let query = TextQuery::parse("café");
+	assert_eq!(query.highlights("Le CAFÉ"), [Range { start: 3, end: 8 }]);
+}
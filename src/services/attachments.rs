@@ -0,0 +1,239 @@
+use actix_multipart::Multipart;
+use actix_web::http::header::{
+	self,
+	CacheControl,
+	CacheDirective,
+};
+use actix_web::http::StatusCode;
+use actix_web::{
+	get,
+	post,
+	web,
+	HttpResponse,
+};
+use futures::TryStreamExt;
+use log::error;
+use mongodb::bson::{
+	doc,
+	DateTime,
+};
+use mongodb::options::{
+	CountOptions,
+	UpdateOptions,
+};
+use mongodb::Database;
+use serde::Serialize;
+
+use crate::api_types::{
+	failure,
+	success,
+	ApiError,
+	ApiResult,
+	Failure,
+};
+use crate::attachments::{
+	self,
+	Format,
+	InvalidImage,
+};
+use crate::auth::AuthenticatedUser;
+use crate::conf;
+use crate::storage::Storage;
+use crate::to_unexpected;
+use crate::types::{
+	Post,
+	PostStatus,
+	Upload,
+};
+
+#[derive(Serialize)]
+pub struct Uploaded {
+	/// The ID to attach the image to a post with.
+	pub id: String,
+	pub width: u32,
+	pub height: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub enum UploadError {
+	InvalidImage(InvalidImage),
+	TooLarge,
+	TooMany,
+}
+
+impl ApiError for UploadError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::InvalidImage(_) => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+			Self::TooMany => StatusCode::BAD_REQUEST,
+		}
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub enum AttachmentError {
+	NotFound,
+}
+
+impl ApiError for AttachmentError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::NotFound => StatusCode::NOT_FOUND,
+		}
+	}
+}
+
+/// Uploads images to attach to a post, as `image` fields of a multipart form.
+///
+/// Images are re-encoded without their metadata. They can be attached to a post by the same user for [`conf::UPLOAD_TTL`].
+#[post("/attachments/")]
+pub async fn upload(
+	db: web::Data<Database>,
+	storage: web::Data<&'static dyn Storage>,
+	user: AuthenticatedUser,
+	mut payload: Multipart,
+) -> ApiResult<Box<[Uploaded]>, UploadError> {
+	let storage: &'static dyn Storage = **storage;
+	let mut uploaded = Vec::new();
+
+	while let Some(mut field) = payload
+		.try_next()
+		.await
+		.map_err(|_| Failure::BadRequest("bad multipart payload"))?
+	{
+		if field.name() != "image" {
+			return Err(Failure::BadRequest("unexpected multipart field"));
+		}
+
+		if uploaded.len() == conf::POST_MAX_ATTACHMENTS {
+			return failure(UploadError::TooMany);
+		}
+
+		let mut data = Vec::new();
+		while let Some(chunk) = field
+			.try_next()
+			.await
+			.map_err(|_| Failure::BadRequest("bad multipart payload"))?
+		{
+			if data.len() + chunk.len() > conf::ATTACHMENT_MAX_SIZE {
+				return failure(UploadError::TooLarge);
+			}
+			data.extend_from_slice(&chunk);
+		}
+
+		let image = web::block(move || attachments::process(&data))
+			.await
+			.map_err(to_unexpected!("Processing image failed"))?
+			.map_err(|err| Failure::Expected(UploadError::InvalidImage(err)))?;
+		let name = image.name();
+		let (width, height) = (image.width, image.height);
+
+		{
+			let name = name.clone();
+			web::block(move || storage.put(&name, &image.data))
+				.await
+				.map_err(to_unexpected!("Storing image failed"))?
+				.map_err(to_unexpected!("Storing image failed"))?;
+		}
+
+		db.collection::<Upload>("uploads")
+			.update_one(
+				doc! {
+					"owner": {"$eq": user.id},
+					"name": {"$eq": &name},
+				},
+				doc! {
+					"$set": {
+						"width": width,
+						"height": height,
+						"created_at": DateTime::now(),
+					},
+				},
+				UpdateOptions::builder().upsert(true).build(),
+			)
+			.await
+			.map_err(to_unexpected!("Recording upload failed"))?;
+
+		uploaded.push(Uploaded {
+			id: name,
+			width,
+			height,
+		});
+	}
+
+	success(uploaded.into())
+}
+
+/// Gets an image by its content-addressed name.
+///
+/// Images attached to visible posts can be seen by everyone. Other images can only be seen by the users who uploaded them, until they're attached or the upload expires.
+#[get("/attachments/{name}")]
+pub async fn get_attachment(
+	db: web::Data<Database>,
+	storage: web::Data<&'static dyn Storage>,
+	viewer: Option<AuthenticatedUser>,
+	name: web::Path<String>,
+) -> Result<HttpResponse, Failure<AttachmentError>> {
+	let name = name.into_inner();
+	let format = name
+		.split_once('.')
+		.filter(|(hash, _)| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+		.and_then(|(_, extension)| Format::from_extension(extension))
+		.ok_or(Failure::Expected(AttachmentError::NotFound))?;
+
+	let attached = db
+		.collection::<Post>("posts")
+		.count_documents(
+			doc! {
+				"attachments.name": {"$eq": &name},
+				"status": PostStatus::Visible,
+			},
+			CountOptions::builder().limit(1).build(),
+		)
+		.await
+		.map_err(to_unexpected!("Finding image's post failed"))?
+		!= 0;
+
+	let cache_scope = if attached {
+		CacheDirective::Public
+	} else {
+		let viewer = viewer.ok_or(Failure::Expected(AttachmentError::NotFound))?;
+		let uploaded = db
+			.collection::<Upload>("uploads")
+			.count_documents(
+				doc! {
+					"owner": {"$eq": viewer.id},
+					"name": {"$eq": &name},
+					"created_at": {"$gte": attachments::upload_cutoff()},
+				},
+				None,
+			)
+			.await
+			.map_err(to_unexpected!("Finding upload failed"))?
+			!= 0;
+
+		if !uploaded {
+			return Err(Failure::Expected(AttachmentError::NotFound));
+		}
+
+		CacheDirective::Private
+	};
+
+	let data = web::block(move || storage.get(&name))
+		.await
+		.map_err(to_unexpected!("Reading image failed"))?
+		.map_err(to_unexpected!("Reading image failed"))?
+		.ok_or(Failure::Expected(AttachmentError::NotFound))?;
+
+	Ok(HttpResponse::Ok()
+		.content_type(format.content_type())
+		.insert_header(CacheControl(vec![
+			cache_scope,
+			CacheDirective::MaxAge(
+				u32::try_from(conf::ATTACHMENT_CACHE_MAX_AGE.as_secs()).unwrap(),
+			),
+		]))
+		.insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+		.body(data))
+}
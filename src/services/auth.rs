@@ -174,7 +174,13 @@ pub async fn register(
 ) -> ApiResult<(), RegistrationError> {
 	// Check to see if their [`school_id`] is valid.
 	db.collection::<School>("schools")
-		.find_one(doc! {"_id": {"$eq": new_user.school_id.clone()}}, None)
+		.find_one(
+			doc! {
+				"_id": {"$eq": new_user.school_id.clone()},
+				"retired": {"$ne": true},
+			},
+			None,
+		)
 		.await
 		.map_err(to_unexpected!("validating school's existence failed"))?
 		.ok_or(Failure::BadRequest("invalid school id"))?;
@@ -187,6 +193,10 @@ pub async fn register(
 			"faculty": to_bson(&new_user.faculty).map_err(to_unexpected!("Converting faculty to bson failed"))?,
 			"username": to_bson(&new_user.username).map_err(to_unexpected!("Converting username to bson failed"))?,
 			"watched_school_ids": to_bson::<Vec<String>>(&vec![]).map_err(to_unexpected!("Converting empty vector to bson failed"))?,
+			"muted_words": to_bson::<Vec<String>>(&vec![]).map_err(to_unexpected!("Converting empty vector to bson failed"))?,
+			"post_count": 0_i64,
+			"upvotes_received": 0_i64,
+			"karma": 0_i64,
 			"school_id": &new_user.school_id,
 		},
 		None
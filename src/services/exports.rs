@@ -0,0 +1,369 @@
+use std::error::Error;
+
+use actix_web::http::header::{
+	self,
+	CacheControl,
+	CacheDirective,
+	ContentDisposition,
+	DispositionParam,
+	DispositionType,
+};
+use actix_web::http::StatusCode;
+use actix_web::{
+	get,
+	post,
+	web,
+	HttpResponse,
+};
+use futures::TryStreamExt;
+use log::error;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{
+	doc,
+	Bson,
+	DateTime,
+	Document,
+};
+use mongodb::options::{
+	FindOneOptions,
+	FindOptions,
+};
+use mongodb::Database;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+use serde_json::json;
+
+use crate::api_types::{
+	failure,
+	success,
+	ApiError,
+	ApiResult,
+	Failure,
+};
+use crate::auth::AuthenticatedUser;
+use crate::conf;
+use crate::exports::{
+	self,
+	LinkSigner,
+};
+use crate::masked_oid::{
+	self,
+	MaskedObjectId,
+	MaskingKey,
+};
+use crate::storage::Storage;
+use crate::to_unexpected;
+use crate::types::{
+	Export,
+	ExportStatus,
+};
+
+#[derive(Serialize)]
+pub struct ExportLink {
+	/// The path of the archive, relative to the API. Returns `NotReady` until the archive has been assembled.
+	pub url: String,
+	pub expires_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct DownloadQuery {
+	/// In milliseconds since the Unix epoch.
+	pub expires: i64,
+	#[serde(with = "crate::base64_serde")]
+	pub signature: [u8; 32],
+}
+
+#[derive(Debug, Serialize)]
+pub enum ExportError {
+	/// The user already requested an export within [`conf::EXPORT_INTERVAL`].
+	TooSoon,
+	NotFound,
+	/// The archive is still being assembled.
+	NotReady,
+	Expired,
+	/// Assembling the archive failed. Another export can be requested right away.
+	Failed,
+}
+
+impl ApiError for ExportError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::TooSoon => StatusCode::TOO_MANY_REQUESTS,
+			Self::NotFound => StatusCode::NOT_FOUND,
+			Self::NotReady => StatusCode::CONFLICT,
+			Self::Expired => StatusCode::GONE,
+			Self::Failed => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+}
+
+fn to_json(doc: Document) -> serde_json::Value {
+	Bson::Document(doc).into_relaxed_extjson()
+}
+
+/// Gets the user's documents from a collection, as JSON.
+async fn user_documents(
+	db: &Database,
+	collection: &str,
+	filter: Document,
+	projection: Document,
+) -> mongodb::error::Result<Vec<serde_json::Value>> {
+	db.collection::<Document>(collection)
+		.find(
+			filter,
+			FindOptions::builder().projection(projection).build(),
+		)
+		.await?
+		.map_ok(to_json)
+		.try_collect()
+		.await
+}
+
+/// Assembles the archive of a user's personal data.
+async fn assemble(db: &Database, user_id: ObjectId) -> Result<Vec<u8>, Box<dyn Error>> {
+	let user = db
+		.collection::<Document>("users")
+		.find_one(
+			doc! {"_id": {"$eq": user_id}},
+			FindOneOptions::builder()
+				.projection(doc! {"last_export_at": false})
+				.build(),
+		)
+		.await?
+		.ok_or("no such user")?;
+
+	let watched_school_ids = user
+		.get_array("watched_school_ids")
+		.cloned()
+		.unwrap_or_default();
+	let watched_schools = user_documents(
+		db,
+		"schools",
+		doc! {"_id": {"$in": watched_school_ids}},
+		doc! {"name": true},
+	)
+	.await?;
+
+	let owned = doc! {"owner": {"$eq": user_id}};
+	let by_user = doc! {"user": {"$eq": user_id}};
+	let archive = json!({
+		"exported_at": DateTime::now().try_to_rfc3339_string()?,
+		"user": to_json(user),
+		// session IDs are hashes of the tokens, and are left out
+		"sessions": user_documents(db, "sessions", by_user.clone(), doc! {"_id": false}).await?,
+		"posts": user_documents(db, "posts", owned.clone(), doc! {}).await?,
+		"votes": user_documents(db, "votes", by_user.clone(), doc! {}).await?,
+		"poll_votes": user_documents(db, "poll_votes", by_user.clone(), doc! {}).await?,
+		"saved_posts": user_documents(db, "saves", by_user.clone(), doc! {}).await?,
+		"hidden_posts": user_documents(db, "hides", by_user, doc! {"_id": false}).await?,
+		"uploads": user_documents(db, "uploads", owned, doc! {}).await?,
+		"watched_schools": watched_schools,
+	});
+
+	Ok(serde_json::to_vec_pretty(&archive)?)
+}
+
+/// Assembles and stores the archive of an export, in the background.
+async fn generate(
+	db: Database,
+	storage: &'static dyn Storage,
+	export_id: ObjectId,
+	user_id: ObjectId,
+) {
+	let archive = match assemble(&db, user_id).await {
+		Ok(archive) => archive,
+		Err(err) => {
+			error!("Assembling export failed: {}", err);
+			exports::fail(&db, export_id, user_id).await;
+			return;
+		}
+	};
+
+	let archive_name = exports::archive_name(&export_id);
+
+	{
+		let archive_name = archive_name.clone();
+		if let Err(err) = web::block(move || storage.put(&archive_name, &archive))
+			.await
+			.map_err(|err| err.to_string())
+			.and_then(|result| result.map_err(|err| err.to_string()))
+		{
+			error!("Storing export archive failed: {}", err);
+			exports::fail(&db, export_id, user_id).await;
+			return;
+		}
+	}
+
+	match db
+		.collection::<Export>("exports")
+		.update_one(
+			doc! {
+				"_id": {"$eq": export_id},
+				"status": ExportStatus::Pending,
+			},
+			doc! {"$set": {
+				"status": ExportStatus::Ready,
+				"archive_name": &archive_name,
+			}},
+			None,
+		)
+		.await
+	{
+		Ok(update_result) if update_result.modified_count == 1 => {}
+		// presumed abandoned and failed in the meantime
+		Ok(_) => {
+			if let Err(err) = web::block(move || storage.delete(&archive_name))
+				.await
+				.map_err(|err| err.to_string())
+				.and_then(|result| result.map_err(|err| err.to_string()))
+			{
+				error!("Deleting abandoned export archive failed: {}", err);
+			}
+		}
+		Err(err) => {
+			error!("Marking export as ready failed: {}", err);
+			exports::fail(&db, export_id, user_id).await;
+		}
+	}
+}
+
+/// Requests an archive of the user's personal data.
+///
+/// The archive is assembled in the background, and can be downloaded from the returned link without authentication until it expires. Only one export can be requested per [`conf::EXPORT_INTERVAL`].
+#[post("/users/export")]
+pub async fn request_export(
+	db: web::Data<Database>,
+	storage: web::Data<&'static dyn Storage>,
+	masking_key: web::Data<&'static MaskingKey>,
+	signer: web::Data<&'static LinkSigner>,
+	user: AuthenticatedUser,
+) -> ApiResult<ExportLink, ExportError> {
+	let now = DateTime::now();
+	let previous_cutoff = DateTime::from_millis(
+		now.timestamp_millis() - i64::try_from(conf::EXPORT_INTERVAL.as_millis()).unwrap(),
+	);
+
+	let claim = db
+		.collection::<Document>("users")
+		.update_one(
+			doc! {
+				"_id": {"$eq": user.id},
+				"$or": [
+					{"last_export_at": {"$exists": false}},
+					{"last_export_at": {"$lte": previous_cutoff}},
+				],
+			},
+			doc! {"$set": {"last_export_at": now}},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Claiming export failed"))?;
+
+	if claim.matched_count != 1 {
+		return failure(ExportError::TooSoon);
+	}
+
+	let expires_at = DateTime::from_millis(
+		now.timestamp_millis() + i64::try_from(conf::EXPORT_TTL.as_millis()).unwrap(),
+	);
+	let export = Export {
+		id: ObjectId::new(),
+		user: user.id,
+		status: ExportStatus::Pending,
+		archive_name: None,
+		expires_at,
+	};
+
+	db.collection::<Export>("exports")
+		.insert_one(&export, None)
+		.await
+		.map_err(to_unexpected!("Creating export failed"))?;
+
+	actix_web::rt::spawn(generate(
+		db.get_ref().clone(),
+		**storage,
+		export.id,
+		user.id,
+	));
+
+	let expires = expires_at.timestamp_millis();
+	success(ExportLink {
+		url: format!(
+			"/exports/{}?expires={}&signature={}",
+			masking_key.mask(&export.id),
+			expires,
+			base64::encode_config(signer.sign(&export.id, expires), base64::URL_SAFE_NO_PAD),
+		),
+		expires_at: expires_at
+			.try_to_rfc3339_string()
+			.map_err(to_unexpected!("Formatting export expiry failed"))?,
+	})
+}
+
+/// Downloads the archive of an export through a signed link.
+#[get("/exports/{export_id}")]
+pub async fn download_export(
+	db: web::Data<Database>,
+	storage: web::Data<&'static dyn Storage>,
+	masking_key: web::Data<&'static MaskingKey>,
+	signer: web::Data<&'static LinkSigner>,
+	export_id: web::Path<MaskedObjectId>,
+	query: web::Query<DownloadQuery>,
+) -> Result<HttpResponse, Failure<ExportError>> {
+	let export_id = masking_key
+		.unmask(&export_id)
+		.map_err(|masked_oid::PaddingError| Failure::Expected(ExportError::NotFound))?;
+
+	if !signer.verify(&export_id, query.expires, &query.signature) {
+		return Err(Failure::Expected(ExportError::NotFound));
+	}
+
+	if query.expires <= DateTime::now().timestamp_millis() {
+		return Err(Failure::Expected(ExportError::Expired));
+	}
+
+	let export = db
+		.collection::<Export>("exports")
+		.find_one(doc! {"_id": {"$eq": export_id}}, None)
+		.await
+		.map_err(to_unexpected!("Finding export failed"))?
+		// removed by `exports::clean_up` after it expired
+		.ok_or(Failure::Expected(ExportError::Expired))?;
+
+	let archive_name = match (export.status, export.archive_name) {
+		(ExportStatus::Pending, _) => return Err(Failure::Expected(ExportError::NotReady)),
+		(ExportStatus::Failed, _) => return Err(Failure::Expected(ExportError::Failed)),
+		(ExportStatus::Ready, Some(archive_name)) => archive_name,
+		(ExportStatus::Ready, None) => {
+			error!("Export is ready without an archive");
+			return Err(Failure::Unexpected);
+		}
+	};
+
+	let storage: &'static dyn Storage = **storage;
+	let archive = web::block(move || storage.get(&archive_name))
+		.await
+		.map_err(to_unexpected!("Reading export archive failed"))?
+		.map_err(to_unexpected!("Reading export archive failed"))?
+		.ok_or_else(|| {
+			error!("Export archive is missing");
+			Failure::Unexpected
+		})?;
+
+	Ok(HttpResponse::Ok()
+		.content_type("application/json")
+		.insert_header(ContentDisposition {
+			disposition: DispositionType::Attachment,
+			parameters: vec![DispositionParam::Filename(String::from(
+				"confesi-export.json",
+			))],
+		})
+		.insert_header(CacheControl(vec![
+			CacheDirective::NoStore,
+			CacheDirective::Private,
+		]))
+		.insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+		.body(archive))
+}
@@ -0,0 +1,52 @@
+use actix_web::http::StatusCode;
+use actix_web::{
+	get,
+	web,
+};
+use log::error;
+use mongodb::bson::doc;
+use mongodb::Database;
+use serde::Serialize;
+
+use crate::api_types::{
+	success,
+	ApiError,
+	ApiResult,
+	Failure,
+};
+use crate::geoip::GeoIp;
+
+#[derive(Serialize)]
+pub struct Readiness {
+	/// When the loaded GeoIP database was built, in seconds since the Unix epoch. `None` if none is loaded, in which case schools aren't sorted by the requester's location.
+	pub geoip_build_epoch: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum ReadinessError {
+	DatabaseUnavailable,
+}
+
+impl ApiError for ReadinessError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::DatabaseUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+		}
+	}
+}
+
+/// Checks that the server can handle requests.
+#[get("/health/ready")]
+pub async fn ready(
+	db: web::Data<Database>,
+	geoip: web::Data<&'static GeoIp>,
+) -> ApiResult<Readiness, ReadinessError> {
+	if let Err(err) = db.run_command(doc! {"ping": 1}, None).await {
+		error!("Pinging database failed: {}", err);
+		return Err(Failure::Expected(ReadinessError::DatabaseUnavailable));
+	}
+
+	success(Readiness {
+		geoip_build_epoch: geoip.build_epoch(),
+	})
+}
@@ -1,120 +1,278 @@
+pub mod attachments;
 pub mod auth;
+pub mod exports;
+pub mod health;
 pub mod posts;
 pub mod profile;
+pub mod schools;
+pub mod verification;
 
+use actix_web::get;
 use actix_web::web;
-use actix_web::{
-	get,
-	HttpRequest,
+use futures::{
+	future,
+	TryStreamExt,
 };
-use futures::TryStreamExt;
 use log::{
 	error,
 	info,
 	warn,
 };
 use maxminddb::geoip2;
-use mongodb::bson::document::ValueAccessError;
 use mongodb::bson::{
 	doc,
-	Document,
+	from_document,
 };
 use mongodb::Database;
-use serde::Serialize;
+use serde::{
+	Deserialize,
+	Serialize,
+};
 
 use crate::api_types::{
 	success,
 	ApiResult,
 	Failure,
 };
+use crate::client_ip::ClientIp;
+use crate::geoip::GeoIp;
+use crate::schools::{
+	fold,
+	is_valid_position,
+};
 use crate::types::School;
 use crate::{
+	conf,
 	to_unexpected,
-	GeoIpReader,
 };
 
+#[derive(Deserialize)]
+pub struct SchoolsQuery {
+	/// Matched case- and accent-insensitively against the start of the words of schools' IDs, names and aliases.
+	pub q: Option<String>,
+	/// The requester's latitude, taking precedence over their IP address. Requires `lon`.
+	pub lat: Option<f64>,
+	/// The requester's longitude, taking precedence over their IP address. Requires `lat`.
+	pub lon: Option<f64>,
+	pub limit: Option<u16>,
+	/// The `cursor` of the last school of the previous page.
+	pub after: Option<String>,
+}
+
 #[derive(Serialize)]
 pub struct SchoolListing {
+	pub cursor: String,
+	pub id: String,
+	pub name: String,
+	/// Distance in kilometres, to the nearest of the school and its listed campuses.
+	pub distance: Option<f32>,
+	/// The school's campuses that match the search, nearest first if the requester's location is known.
+	pub campuses: Vec<CampusListing>,
+}
+
+#[derive(Serialize)]
+pub struct CampusListing {
 	pub id: String,
 	pub name: String,
 	/// Distance in kilometres.
 	pub distance: Option<f32>,
 }
 
+#[derive(Deserialize)]
+struct ListedSchool {
+	#[serde(rename = "_id")]
+	id: String,
+	name: String,
+	distance: Option<f64>,
+	campuses: Vec<ListedCampus>,
+}
+
+#[derive(Deserialize)]
+struct ListedCampus {
+	#[serde(rename = "_id")]
+	id: String,
+	name: String,
+	distance: Option<f64>,
+}
+
 struct GeoPoint {
 	longitude: f32,
 	latitude: f32,
 }
 
+/// Where to continue listing schools from: after the school with the given ID, and the given distance when listing by distance.
+fn parse_cursor(cursor: &str) -> Option<(Option<f64>, &str)> {
+	match cursor.split_once(':') {
+		Some((distance, id)) => Some((Some(distance.parse().ok()?), id)),
+		None => Some((None, cursor)),
+	}
+}
+
+/// Lists schools with their campuses, nearest first if the requester's location is known, and by ID otherwise. Retired schools aren't listed, nor are the campuses of retired schools.
 #[get("/schools/")]
 pub async fn schools_list(
-	req: HttpRequest,
+	client_ip: Option<ClientIp>,
 	db: web::Data<Database>,
-	geoip: web::Data<Option<GeoIpReader>>,
+	geoip: web::Data<&'static GeoIp>,
+	query: web::Query<SchoolsQuery>,
 ) -> ApiResult<Box<[SchoolListing]>, ()> {
+	let limit = query.limit.unwrap_or(conf::SCHOOLS_PAGE_SIZE);
+	if !(1..=conf::SCHOOLS_PAGE_SIZE_MAX).contains(&limit) {
+		return Err(Failure::BadRequest("invalid limit"));
+	}
+
 	let mut location: Option<GeoPoint> = None;
 
-	if let Some(geoip) = geoip.get_ref() {
-		if let Some(peer_addr) = req.peer_addr() {
-			match geoip.lookup::<geoip2::City>(peer_addr.ip()) {
-				Ok(city) => {
-					location = city.location.and_then(|location| {
-						Some(GeoPoint {
-							longitude: location.longitude? as f32,
-							latitude: location.latitude? as f32,
-						})
-					});
-				}
-				Err(err) => {
-					info!("Not using location for schools list: {}", err);
+	match (query.lat, query.lon) {
+		(Some(latitude), Some(longitude)) => {
+			if !is_valid_position(longitude, latitude) {
+				return Err(Failure::BadRequest("position out of range"));
+			}
+
+			location = Some(GeoPoint {
+				longitude: longitude as f32,
+				latitude: latitude as f32,
+			});
+		}
+		(Some(_), None) | (None, Some(_)) => {
+			return Err(Failure::BadRequest("lat and lon must be given together"));
+		}
+		(None, None) => {
+			if let Some(geoip) = geoip.reader() {
+				if let Some(ClientIp(ip)) = client_ip {
+					match geoip.lookup::<geoip2::City>(ip) {
+						Ok(city) => {
+							location = city.location.and_then(|location| {
+								Some(GeoPoint {
+									longitude: location.longitude? as f32,
+									latitude: location.latitude? as f32,
+								})
+							});
+						}
+						Err(err) => {
+							info!("Not using location for schools list: {}", err);
+						}
+					}
+				} else {
+					warn!("Not using location for schools list: no peer address");
 				}
 			}
-		} else {
-			warn!("Not using location for schools list: no peer address");
 		}
 	}
 
-	let operator = match location {
-		Some(location) => doc! {
-			"$geoNear": {
-				"distanceField": "distance",
-				"distanceMultiplier": 1e-3,
-				"near": {
-					"type": "Point",
-					"coordinates": [location.longitude, location.latitude],
+	let mut filter = doc! {"retired": {"$ne": true}};
+
+	if let Some(q) = &query.q {
+		if q.len() > conf::SEARCH_QUERY_MAX_SIZE {
+			return Err(Failure::BadRequest("search query too long"));
+		}
+
+		let q = fold(q);
+		if !q.is_empty() {
+			filter.insert(
+				"search_keys",
+				doc! {"$regex": format!("^{}", regex::escape(&q))},
+			);
+		}
+	}
+
+	let after = query
+		.after
+		.as_deref()
+		.map(|after| parse_cursor(after).ok_or(Failure::BadRequest("bad cursor")))
+		.transpose()?;
+
+	let mut pipeline = match &location {
+		Some(location) => vec![
+			doc! {
+				"$geoNear": {
+					"distanceField": "distance",
+					"distanceMultiplier": 1e-3,
+					"near": {
+						"type": "Point",
+						"coordinates": [location.longitude, location.latitude],
+					},
+					"spherical": true,
+					"query": filter,
 				},
-				"spherical": true,
 			},
-		},
-		None => doc! {
-			"$project": {
-				"name": true,
-				"distance": {"$literal": null},
-			}
-		},
+			doc! {"$sort": {"distance": 1, "_id": 1}},
+		],
+		None => vec![doc! {"$match": filter}, doc! {"$sort": {"_id": 1}}],
 	};
 
+	// campuses are grouped under their parent, which is ranked by its nearest matching campus, counting itself as one
+	pipeline.extend([
+		doc! {"$group": {
+			"_id": {"$ifNull": ["$parent_id", "$_id"]},
+			"distance": {"$min": "$distance"},
+			"campuses": {"$push": {"$cond": [
+				{"$eq": [{"$ifNull": ["$parent_id", null]}, null]},
+				null,
+				{"_id": "$_id", "name": "$name", "distance": "$distance"},
+			]}},
+		}},
+		doc! {"$lookup": {
+			"from": "schools",
+			"localField": "_id",
+			"foreignField": "_id",
+			"as": "parent",
+		}},
+		doc! {"$unwind": "$parent"},
+		doc! {"$match": {"parent.retired": {"$ne": true}}},
+	]);
+
+	match (location.is_some(), after) {
+		(_, None) => {}
+		(true, Some((Some(distance), id))) => pipeline.push(doc! {
+			"$match": {
+				"$or": [
+					{"distance": {"$gt": distance}},
+					{"distance": {"$eq": distance}, "_id": {"$gt": id}},
+				],
+			},
+		}),
+		(false, Some((None, id))) => pipeline.push(doc! {"$match": {"_id": {"$gt": id}}}),
+		_ => return Err(Failure::BadRequest("bad cursor")),
+	}
+
+	pipeline.extend([
+		doc! {"$sort": {"distance": 1, "_id": 1}},
+		doc! {"$limit": i64::from(limit)},
+		doc! {"$project": {
+			"name": "$parent.name",
+			"distance": true,
+			"campuses": {"$filter": {"input": "$campuses", "cond": {"$ne": ["$$this", null]}}},
+		}},
+	]);
+
 	success(
 		db.collection::<School>("schools")
-			.aggregate([operator], None)
+			.aggregate(pipeline, None)
 			.await
 			.map_err(to_unexpected!("Getting list of schools cursor failed"))?
-			.map_ok(|doc: Document| -> Result<SchoolListing, ValueAccessError> {
-				Ok(SchoolListing {
-					id: String::from(doc.get_str("_id")?),
-					name: String::from(doc.get_str("name")?),
-					distance: if doc.is_null("distance") {
-						None
-					} else {
-						Some(doc.get_f64("distance")? as f32)
-					},
-				})
+			.and_then(|doc| future::ready(from_document::<ListedSchool>(doc).map_err(Into::into)))
+			.map_ok(|school| SchoolListing {
+				cursor: match school.distance {
+					Some(distance) => format!("{}:{}", distance, school.id),
+					None => school.id.clone(),
+				},
+				id: school.id,
+				name: school.name,
+				distance: school.distance.map(|distance| distance as f32),
+				campuses: school
+					.campuses
+					.into_iter()
+					.map(|campus| CampusListing {
+						id: campus.id,
+						name: campus.name,
+						distance: campus.distance.map(|distance| distance as f32),
+					})
+					.collect(),
 			})
-			.try_collect::<Vec<Result<SchoolListing, ValueAccessError>>>()
+			.try_collect::<Vec<SchoolListing>>()
 			.await
 			.map_err(to_unexpected!("Reading list of schools cursor failed"))?
-			.into_iter()
-			.collect::<Result<Box<[SchoolListing]>, _>>()
-			.map_err(to_unexpected!("Deserializing school listing failed"))?,
+			.into(),
 	)
 }
@@ -1,10 +1,18 @@
+use std::collections::{
+	HashMap,
+	HashSet,
+};
 use std::convert::TryFrom;
 
+use actix_web::http::StatusCode;
 use actix_web::web;
 use actix_web::{
+	delete,
 	get,
 	post,
 	put,
+	HttpRequest,
+	HttpResponse,
 };
 use futures::TryStreamExt;
 use log::{
@@ -12,8 +20,11 @@ use log::{
 	error,
 	info,
 };
+use mongodb::bson::oid::ObjectId;
 use mongodb::bson::{
 	doc,
+	from_document,
+	to_bson,
 	DateTime,
 	Document,
 };
@@ -22,8 +33,10 @@ use mongodb::error::{
 	WriteFailure,
 };
 use mongodb::options::{
+	FindOneAndUpdateOptions,
 	FindOneOptions,
 	FindOptions,
+	UpdateOptions,
 };
 use mongodb::{
 	Client as MongoClient,
@@ -36,20 +49,52 @@ use serde::{
 
 use crate::api_types::{
 	success,
+	ApiError,
 	ApiResult,
 	Failure,
 };
+use crate::attachments;
 use crate::auth::AuthenticatedUser;
 use crate::conf;
+use crate::content_filter::{
+	words_regex,
+	Category,
+	ContentFilter,
+	Rejected,
+};
+use crate::crisis::CrisisDetector;
+use crate::idempotency;
 use crate::masked_oid::{
 	self,
 	MaskedObjectId,
 	MaskedSequentialId,
 	MaskingKey,
 };
+use crate::ranking::{
+	self,
+	Ranking,
+	VoteChange,
+};
+use crate::schools;
+use crate::search::TextQuery;
 use crate::to_unexpected;
 use crate::types::{
+	Attachment,
+	Hide,
+	Poll,
+	PollOption,
+	PollVote,
 	Post,
+	PostStatus,
+	PosterFaculty,
+	PosterYearOfStudy,
+	Review,
+	ReviewReason,
+	Save,
+	School,
+	SupportContact,
+	Upload,
+	User,
 	Vote,
 };
 
@@ -72,23 +117,301 @@ pub struct Detail {
 	pub text: String,
 	pub created_at: String,
 	pub votes: Votes,
+	pub school_id: String,
+	/// Year of study of the poster, unless it would identify them.
+	pub year_of_study: Option<PosterYearOfStudy>,
+	/// Faculty of the poster, unless it would identify them.
+	pub faculty: Option<PosterFaculty>,
+	/// Whether the poster had verified they belong to the school, with a school email address. Only shown once enough of the school's users have verified.
+	pub verified: bool,
+	pub attachments: Vec<AttachmentDetail>,
+	pub poll: Option<PollDetail>,
+	/// Present if the post indicates self-harm.
+	pub support: Option<SupportResources>,
+}
+
+/// A page of posts.
+#[derive(Serialize)]
+pub struct Page {
+	pub posts: Box<[Detail]>,
+	/// The `before` to get the next page with, when sorting by recent and more posts may follow.
+	///
+	/// Posts that were examined but left out of the page count towards the next page having been reached, so this can be present even if `posts` is short or empty.
+	///
+	/// Feeds sorted by score only have a first page, which can also be short or empty when posts are left out, and never have a `next`.
+	pub next: Option<MaskedSequentialId>,
+}
+
+#[derive(Serialize)]
+pub struct AttachmentDetail {
+	/// The path of the image, relative to the API.
+	pub url: String,
+	pub width: u32,
+	pub height: u32,
+	pub alt_text: String,
+}
+
+#[derive(Serialize)]
+pub struct PollDetail {
+	pub options: Vec<PollOptionDetail>,
+	pub closes_at: Option<String>,
+	pub closed: bool,
+	/// The index of the option chosen by the requesting user, if they're authenticated and have voted.
+	pub choice: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct PollOptionDetail {
+	pub text: String,
+	pub votes: u32,
+}
+
+impl PollDetail {
+	fn from_poll<E: ApiError>(poll: Poll, choice: Option<u32>) -> Result<Self, Failure<E>> {
+		Ok(Self {
+			closed: poll.is_closed(),
+			options: poll
+				.options
+				.into_iter()
+				.map(|option| PollOptionDetail {
+					text: option.text,
+					votes: u32::try_from(option.votes).unwrap(),
+				})
+				.collect(),
+			closes_at: poll
+				.closes_at
+				.map(|closes_at| closes_at.try_to_rfc3339_string())
+				.transpose()
+				.map_err(to_unexpected!("Formatting poll closing time failed"))?,
+			choice,
+		})
+	}
+}
+
+/// Where to get help, shown alongside posts indicating self-harm.
+#[derive(Clone, Serialize)]
+pub struct SupportResources {
+	/// The counselling service of the post's school, if known.
+	pub school: Option<SupportContact>,
+	pub crisis_lines: Vec<SupportContact>,
+}
+
+impl Detail {
+	fn from_post(
+		masking_key: &MaskingKey,
+		post: Post,
+		poll_choice: Option<u32>,
+		support: Option<SupportResources>,
+	) -> Result<Self, Failure<()>> {
+		Ok(Self {
+			id: masking_key.mask(&post.id),
+			sequential_id: masking_key.mask_sequential(u64::try_from(post.sequential_id).unwrap()),
+			reply_context: None,
+			text: post.text,
+			created_at: (post
+				.id
+				.timestamp()
+				.try_to_rfc3339_string()
+				.map_err(to_unexpected!("Formatting post timestamp failed"))?),
+			votes: Votes {
+				up: u32::try_from(post.votes_up).unwrap(),
+				down: u32::try_from(post.votes_down).unwrap(),
+			},
+			school_id: post.school_id,
+			year_of_study: post.year_of_study,
+			faculty: post.faculty,
+			verified: post.verified,
+			attachments: post
+				.attachments
+				.into_iter()
+				.map(|attachment| AttachmentDetail {
+					url: format!("/attachments/{}", attachment.name),
+					width: attachment.width,
+					height: attachment.height,
+					alt_text: attachment.alt_text,
+				})
+				.collect(),
+			poll: post
+				.poll
+				.map(|poll| PollDetail::from_poll(poll, poll_choice))
+				.transpose()?,
+			support,
+		})
+	}
+}
+
+/// Converts posts to their [`Detail`]s, looking up support resources for those indicating self-harm and the viewer's choices in polls.
+async fn details(
+	db: &Database,
+	masking_key: &MaskingKey,
+	crisis_detector: &CrisisDetector,
+	viewer: Option<&AuthenticatedUser>,
+	posts: Vec<Post>,
+) -> Result<Vec<Detail>, Failure<()>> {
+	let poll_post_ids = posts
+		.iter()
+		.filter(|post| post.poll.is_some())
+		.map(|post| post.id)
+		.collect::<Vec<ObjectId>>();
+
+	let poll_choices = match viewer {
+		Some(viewer) if !poll_post_ids.is_empty() => db
+			.collection::<PollVote>("poll_votes")
+			.find(
+				doc! {
+					"post": {"$in": poll_post_ids},
+					"user": {"$eq": viewer.id},
+				},
+				None,
+			)
+			.await
+			.map_err(to_unexpected!("Getting poll choices cursor failed"))?
+			.map_ok(|poll_vote| (poll_vote.post, poll_vote.option))
+			.try_collect::<HashMap<ObjectId, u32>>()
+			.await
+			.map_err(to_unexpected!("Getting poll choices failed"))?,
+		_ => HashMap::new(),
+	};
+
+	let flagged_school_ids = posts
+		.iter()
+		.filter(|post| post.crisis_flagged)
+		.map(|post| post.school_id.clone())
+		.collect::<HashSet<String>>();
+
+	let counselling = if flagged_school_ids.is_empty() {
+		HashMap::new()
+	} else {
+		db.collection::<School>("schools")
+			.find(
+				doc! {"_id": {"$in": Vec::from_iter(flagged_school_ids)}},
+				None,
+			)
+			.await
+			.map_err(to_unexpected!("Getting school counselling cursor failed"))?
+			.map_ok(|school| (school.id, school.counselling))
+			.try_collect::<HashMap<String, Option<SupportContact>>>()
+			.await
+			.map_err(to_unexpected!("Getting school counselling failed"))?
+	};
+
+	posts
+		.into_iter()
+		.map(|post| {
+			let support = post.crisis_flagged.then(|| SupportResources {
+				school: counselling.get(&post.school_id).cloned().flatten(),
+				crisis_lines: crisis_detector.crisis_lines.clone(),
+			});
+			let poll_choice = poll_choices.get(&post.id).copied();
+			Detail::from_post(masking_key, post, poll_choice, support)
+		})
+		.collect()
+}
+
+#[derive(Deserialize)]
+pub struct ListQuery {
+	#[serde(flatten)]
+	pub sort: ListSort,
+	/// Comma-separated IDs of the schools to list posts from, along with their campuses. All schools are included if absent.
+	pub school_ids: Option<String>,
+	pub faculty: Option<PosterFaculty>,
+	pub year_of_study: Option<PosterYearOfStudy>,
 }
 
 #[derive(Deserialize)]
 #[serde(tag = "sort", rename_all = "kebab-case")]
-pub enum ListQuery {
-	Recent { before: Option<MaskedSequentialId> },
+pub enum ListSort {
+	Recent {
+		before: Option<MaskedSequentialId>,
+	},
+	#[serde(alias = "hot")]
 	Trending,
+	Best,
+	Controversial,
+	Rising,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SearchOrder {
+	#[default]
+	Relevance,
+	Recent,
 }
 
 #[derive(Deserialize)]
+pub struct SearchQuery {
+	pub q: String,
+	/// Comma-separated IDs of the schools to search within, along with their campuses. All schools are searched if absent.
+	pub school_ids: Option<String>,
+	#[serde(default)]
+	pub order: SearchOrder,
+	pub before: Option<MaskedSequentialId>,
+	/// The relevance of the last result of the previous page. Required along with `before` when ordering by relevance.
+	pub before_relevance: Option<f64>,
+}
+
+/// A match in a post's text, in UTF-8 bytes.
+#[derive(Serialize)]
+pub struct Highlight {
+	pub start: usize,
+	pub end: usize,
+}
+
+#[derive(Serialize)]
+pub struct SearchResult {
+	#[serde(flatten)]
+	pub post: Detail,
+	pub relevance: f64,
+	pub highlights: Box<[Highlight]>,
+}
+
+#[derive(Deserialize, Serialize)]
 pub struct CreateRequest {
 	pub text: String,
+	#[serde(default)]
+	pub attachments: Vec<NewAttachment>,
+	pub poll: Option<NewPoll>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NewAttachment {
+	/// The ID of an image uploaded by the poster.
+	pub id: String,
+	pub alt_text: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct NewPoll {
+	pub options: Vec<String>,
+	/// An RFC 3339 timestamp. The poll stays open indefinitely if absent.
+	pub closes_at: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct Created {
 	pub id: MaskedObjectId,
+	/// Whether the post is being held for review by moderators before it's published.
+	pub pending_review: bool,
+	/// Present if the post indicates self-harm.
+	pub support: Option<SupportResources>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum CreateError {
+	/// The text was refused by a content filter rule of the given category.
+	ContentRejected(Category),
+	/// The school requires posters to verify they belong to it first.
+	VerificationRequired,
+}
+
+impl ApiError for CreateError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::ContentRejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::VerificationRequired => StatusCode::FORBIDDEN,
+		}
+	}
 }
 
 /// Route for retrieving a post by a specific masked ID.
@@ -96,6 +419,8 @@ pub struct Created {
 pub async fn get_single_post(
 	db: web::Data<Database>,
 	masking_key: web::Data<&'static MaskingKey>,
+	crisis_detector: web::Data<&'static CrisisDetector>,
+	viewer: Option<AuthenticatedUser>,
 	post_id: web::Path<MaskedObjectId>,
 ) -> ApiResult<Box<Detail>, ()> {
 	// Unmask the ID, in order for it to be used for querying.
@@ -105,7 +430,13 @@ pub async fn get_single_post(
 	// Query the database for the post.
 	let possible_post = db
 		.collection::<Post>("posts")
-		.find_one(doc! {"_id": post_id}, None)
+		.find_one(
+			doc! {
+				"_id": post_id,
+				"status": PostStatus::Visible,
+			},
+			None,
+		)
 		.await;
 	let post: Post;
 	// Return 400 if the post doesn't exist, 500 if there's a query error, or the [`Detail`] post itself
@@ -117,34 +448,42 @@ pub async fn get_single_post(
 		},
 		Err(_) => return Err(Failure::Unexpected),
 	};
-	success(Box::new(Detail {
-		id: masking_key.mask(&post.id),
-		sequential_id: masking_key.mask_sequential(u64::try_from(post.sequential_id).unwrap()),
-		reply_context: None,
-		text: post.text,
-		created_at: (post
-			.id
-			.timestamp()
-			.try_to_rfc3339_string()
-			.map_err(to_unexpected!("Formatting post timestamp failed"))?),
-		votes: Votes {
-			up: u32::try_from(post.votes_up).unwrap(),
-			down: u32::try_from(post.votes_down).unwrap(),
-		},
-	}))
+	success(Box::new(
+		details(
+			&db,
+			&masking_key,
+			&crisis_detector,
+			viewer.as_ref(),
+			vec![post],
+		)
+		.await?
+		.pop()
+		.unwrap(),
+	))
+}
+
+/// Matches the `school_id` of posts against a comma-separated list of school IDs, including the campuses of those schools.
+async fn school_ids_filter(db: &Database, school_ids: &str) -> Result<Document, Failure<()>> {
+	let school_ids = schools::with_campuses(db, school_ids.split(',').map(String::from).collect())
+		.await
+		.map_err(to_unexpected!("Finding campuses failed"))?;
+
+	Ok(doc! {"$in": school_ids})
 }
 
 #[get("/posts/")]
 pub async fn list(
 	db: web::Data<Database>,
 	masking_key: web::Data<&'static MaskingKey>,
+	crisis_detector: web::Data<&'static CrisisDetector>,
+	viewer: Option<AuthenticatedUser>,
 	query: web::Query<ListQuery>,
-) -> ApiResult<Box<[Detail]>, ()> {
-	let find_query;
+) -> ApiResult<Page, ()> {
+	let mut find_query;
 	let sort;
 
-	match &*query {
-		ListQuery::Recent { before } => {
+	match &query.sort {
+		ListSort::Recent { before } => {
 			find_query = match before {
 				None => doc! {},
 				Some(before) => {
@@ -159,77 +498,611 @@ pub async fn list(
 
 			sort = doc! {"sequential_id": -1};
 		}
-		ListQuery::Trending => {
+		ListSort::Trending => {
+			find_query = doc! {};
+			sort = doc! {ranking::Hot.score_field(): -1};
+		}
+		ListSort::Best => {
 			find_query = doc! {};
-			sort = doc! {"trending_score": -1};
+			sort = doc! {ranking::Best.score_field(): -1};
+		}
+		ListSort::Controversial => {
+			find_query = doc! {};
+			sort = doc! {ranking::Controversial.score_field(): -1};
+		}
+		ListSort::Rising => {
+			find_query = doc! {};
+			sort = doc! {ranking::Rising.score_field(): -1};
 		}
 	}
 
-	let posts = db
+	find_query.insert("status", PostStatus::Visible);
+
+	if let Some(school_ids) = &query.school_ids {
+		find_query.insert("school_id", school_ids_filter(&db, school_ids).await?);
+	}
+
+	if let Some(faculty) = &query.faculty {
+		find_query.insert(
+			"faculty",
+			to_bson(faculty).map_err(to_unexpected!("Converting faculty to bson failed"))?,
+		);
+	}
+
+	if let Some(year_of_study) = &query.year_of_study {
+		find_query.insert(
+			"year_of_study",
+			to_bson(year_of_study)
+				.map_err(to_unexpected!("Converting year of study to bson failed"))?,
+		);
+	}
+
+	let muted_words = match &viewer {
+		Some(viewer) => {
+			let muted_words = db
+				.collection::<User>("users")
+				.find_one(doc! {"_id": {"$eq": viewer.id}}, None)
+				.await
+				.map_err(to_unexpected!("Finding viewer failed"))?
+				.ok_or(Failure::BadRequest("no account matches this id"))?
+				.muted_words;
+
+			(!muted_words.is_empty())
+				.then(|| words_regex(&muted_words))
+				.transpose()
+				.map_err(to_unexpected!("Compiling muted words failed"))?
+		}
+		None => None,
+	};
+
+	// Authenticated users' hidden posts and muted words are filtered out here, so more posts than a page may need to be examined to fill one.
+	let page_size = usize::from(conf::POSTS_PAGE_SIZE);
+	let scan_limit = if viewer.is_some() {
+		conf::POSTS_SCAN_LIMIT
+	} else {
+		conf::POSTS_PAGE_SIZE
+	};
+	let mut cursor = db
 		.collection::<Post>("posts")
 		.find(
 			find_query,
 			FindOptions::builder()
 				.sort(sort)
-				.limit(i64::from(conf::POSTS_PAGE_SIZE))
+				.limit(i64::from(scan_limit))
+				.batch_size(u32::from(conf::POSTS_PAGE_SIZE))
 				.build(),
 		)
 		.await
-		.map_err(to_unexpected!("Getting posts cursor failed"))?
-		.map_ok(|post| {
-			Ok(Detail {
-				id: masking_key.mask(&post.id),
-				sequential_id: masking_key
-					.mask_sequential(u64::try_from(post.sequential_id).unwrap()),
-				reply_context: None,
-				text: post.text,
-				created_at: (post
-					.id
-					.timestamp()
-					.try_to_rfc3339_string()
-					.map_err(to_unexpected!("Formatting post timestamp failed"))?),
-				votes: Votes {
-					up: u32::try_from(post.votes_up).unwrap(),
-					down: u32::try_from(post.votes_down).unwrap(),
+		.map_err(to_unexpected!("Getting posts cursor failed"))?;
+
+	let mut posts = Vec::with_capacity(page_size);
+	let mut scanned = 0;
+	let mut exhausted = false;
+	let mut last_examined = None;
+	while posts.len() < page_size {
+		let mut batch = Vec::with_capacity(page_size);
+		while batch.len() < page_size {
+			match cursor
+				.try_next()
+				.await
+				.map_err(to_unexpected!("Getting posts failed"))?
+			{
+				Some(post) => batch.push(post),
+				None => {
+					exhausted = scanned < scan_limit;
+					break;
+				}
+			}
+			scanned += 1;
+		}
+
+		if batch.is_empty() {
+			break;
+		}
+
+		let hidden = match &viewer {
+			Some(viewer) => db
+				.collection::<Hide>("hides")
+				.find(
+					doc! {
+						"user": {"$eq": viewer.id},
+						"post": {"$in": batch.iter().map(|post| post.id).collect::<Vec<ObjectId>>()},
+					},
+					None,
+				)
+				.await
+				.map_err(to_unexpected!("Getting hidden posts cursor failed"))?
+				.map_ok(|hide| hide.post)
+				.try_collect::<HashSet<ObjectId>>()
+				.await
+				.map_err(to_unexpected!("Getting hidden posts failed"))?,
+			None => HashSet::new(),
+		};
+
+		for post in batch {
+			if posts.len() == page_size {
+				// the rest of the batch wasn't examined, and belongs to the next page
+				exhausted = false;
+				break;
+			}
+
+			last_examined = Some(post.sequential_id);
+			if !hidden.contains(&post.id)
+				&& !muted_words
+					.as_ref()
+					.is_some_and(|muted_words| muted_words.is_match(&post.text))
+			{
+				posts.push(post);
+			}
+		}
+	}
+
+	let next = match (&query.sort, exhausted, last_examined) {
+		(ListSort::Recent { .. }, false, Some(last_examined)) => {
+			Some(masking_key.mask_sequential(u64::try_from(last_examined).unwrap()))
+		}
+		_ => None,
+	};
+
+	success(Page {
+		posts: details(&db, &masking_key, &crisis_detector, viewer.as_ref(), posts)
+			.await?
+			.into(),
+		next,
+	})
+}
+
+/// Searches the text of posts.
+///
+/// The query supports the syntax of MongoDB's `$text`: quoted phrases must all match, and terms prefixed with `-` must not match.
+#[get("/posts/search")]
+pub async fn search(
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	crisis_detector: web::Data<&'static CrisisDetector>,
+	viewer: Option<AuthenticatedUser>,
+	query: web::Query<SearchQuery>,
+) -> ApiResult<Box<[SearchResult]>, ()> {
+	if query.q.trim().is_empty() {
+		return Err(Failure::BadRequest("empty search query"));
+	}
+
+	if query.q.len() > conf::SEARCH_QUERY_MAX_SIZE {
+		return Err(Failure::BadRequest("oversized search query"));
+	}
+
+	let mut filter = doc! {
+		"$text": {"$search": &query.q},
+		"status": PostStatus::Visible,
+	};
+
+	if let Some(school_ids) = &query.school_ids {
+		filter.insert("school_id", school_ids_filter(&db, school_ids).await?);
+	}
+
+	let before = query
+		.before
+		.as_ref()
+		.map(|before| {
+			masking_key
+				.unmask_sequential(before)
+				.map(|before| i64::try_from(before).unwrap())
+				.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked sequential id"))
+		})
+		.transpose()?;
+
+	let page_filter;
+	let sort;
+
+	match query.order {
+		SearchOrder::Relevance => {
+			page_filter = match (before, query.before_relevance) {
+				(None, None) => None,
+				(Some(before), Some(before_relevance)) => Some(doc! {
+					"$or": [
+						{"relevance": {"$lt": before_relevance}},
+						{
+							"relevance": {"$eq": before_relevance},
+							"sequential_id": {"$lt": before},
+						},
+					],
+				}),
+				_ => {
+					return Err(Failure::BadRequest(
+						"before and before_relevance must be given together",
+					))
+				}
+			};
+
+			sort = doc! {"relevance": -1, "sequential_id": -1};
+		}
+		SearchOrder::Recent => {
+			page_filter = before.map(|before| doc! {"sequential_id": {"$lt": before}});
+			sort = doc! {"sequential_id": -1};
+		}
+	}
+
+	let mut pipeline = vec![
+		doc! {"$match": filter},
+		doc! {"$addFields": {"relevance": {"$meta": "textScore"}}},
+	];
+	pipeline.extend(page_filter.map(|page_filter| doc! {"$match": page_filter}));
+	pipeline.push(doc! {"$sort": sort});
+	pipeline.push(doc! {"$limit": i64::from(conf::POSTS_PAGE_SIZE)});
+
+	let text_query = TextQuery::parse(&query.q);
+
+	struct Match {
+		relevance: f64,
+		highlights: Box<[Highlight]>,
+	}
+
+	let (matches, posts): (Vec<Match>, Vec<Post>) = db
+		.collection::<Post>("posts")
+		.aggregate(pipeline, None)
+		.await
+		.map_err(to_unexpected!("Getting search results cursor failed"))?
+		.map_ok(|doc| {
+			let relevance = doc
+				.get_f64("relevance")
+				.map_err(to_unexpected!("Reading search result relevance failed"))?;
+			let post = from_document::<Post>(doc)
+				.map_err(to_unexpected!("Deserializing search result failed"))?;
+			let highlights = text_query
+				.highlights(&post.text)
+				.into_iter()
+				.map(|range| Highlight {
+					start: range.start,
+					end: range.end,
+				})
+				.collect();
+
+			Ok((
+				Match {
+					relevance,
+					highlights,
 				},
-			})
+				post,
+			))
 		})
-		.try_collect::<Vec<Result<Detail, Failure<()>>>>()
+		.try_collect::<Vec<Result<_, Failure<()>>>>()
 		.await
-		.map_err(to_unexpected!("Getting posts failed"))?
+		.map_err(to_unexpected!("Getting search results failed"))?
+		.into_iter()
+		.collect::<Result<Vec<_>, Failure<()>>>()?
+		.into_iter()
+		.unzip();
+
+	let results = details(&db, &masking_key, &crisis_detector, viewer.as_ref(), posts)
+		.await?
 		.into_iter()
-		.collect::<Result<Vec<Detail>, Failure<()>>>()?;
+		.zip(matches)
+		.map(|(post, m)| SearchResult {
+			post,
+			relevance: m.relevance,
+			highlights: m.highlights,
+		})
+		.collect::<Vec<SearchResult>>();
+
+	success(results.into())
+}
+
+/// Chooses which of the poster's attributes can be shown on their post without narrowing them down to fewer than [`conf::POSTER_COHORT_MIN_SIZE`] users at their school.
+///
+/// If the combination of year of study and faculty is too rare, the attribute shared with more users is shown alone, if either is common enough.
+async fn disclosed_attributes(
+	db: &Database,
+	owner: &User,
+) -> Result<(Option<PosterYearOfStudy>, Option<PosterFaculty>), Failure<CreateError>> {
+	let users = db.collection::<User>("users");
+	let cohort_size = |year_of_study: Option<&PosterYearOfStudy>,
+	                   faculty: Option<&PosterFaculty>| {
+		let mut filter = doc! {"school_id": {"$eq": &owner.school_id}};
+		if let Some(year_of_study) = year_of_study {
+			filter.insert("year_of_study", to_bson(year_of_study).unwrap());
+		}
+		if let Some(faculty) = faculty {
+			filter.insert("faculty", to_bson(faculty).unwrap());
+		}
+		let users = users.clone();
+		async move {
+			users
+				.count_documents(filter, None)
+				.await
+				.map_err(to_unexpected!("Counting poster cohort failed"))
+		}
+	};
+
+	let year_of_study = owner.year_of_study.as_ref();
+	let faculty = owner.faculty.as_ref();
+
+	if year_of_study.is_some()
+		&& faculty.is_some()
+		&& cohort_size(year_of_study, faculty).await? >= conf::POSTER_COHORT_MIN_SIZE
+	{
+		return Ok((year_of_study.cloned(), faculty.cloned()));
+	}
 
-	success(posts.into())
+	let year_cohort = match year_of_study {
+		Some(year_of_study) => cohort_size(Some(year_of_study), None).await?,
+		None => 0,
+	};
+	let faculty_cohort = match faculty {
+		Some(faculty) => cohort_size(None, Some(faculty)).await?,
+		None => 0,
+	};
+
+	Ok(
+		if year_cohort >= faculty_cohort && year_cohort >= conf::POSTER_COHORT_MIN_SIZE {
+			(year_of_study.cloned(), None)
+		} else if faculty_cohort >= conf::POSTER_COHORT_MIN_SIZE {
+			(None, faculty.cloned())
+		} else {
+			(None, None)
+		},
+	)
 }
 
-/// Gets the time-based offset of the trending score for the given timestamp.
-fn get_trending_score_time(date_time: &DateTime) -> f64 {
-	f64::from(u32::try_from(date_time.timestamp_millis() / 1000 - conf::TRENDING_EPOCH).unwrap())
-		/ conf::TRENDING_DECAY
+/// Whether the poster's verified badge can be shown on their post, without narrowing them down to fewer than [`conf::POSTER_COHORT_MIN_SIZE`] verified users at their school.
+async fn disclosed_verification(db: &Database, owner: &User) -> Result<bool, Failure<CreateError>> {
+	if !owner.is_verified() {
+		return Ok(false);
+	}
+
+	let verified_cohort = db
+		.collection::<User>("users")
+		.count_documents(
+			doc! {
+				"school_id": {"$eq": &owner.school_id},
+				"verified_school_id": {"$eq": &owner.school_id},
+			},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Counting verified poster cohort failed"))?;
+
+	Ok(verified_cohort >= conf::POSTER_COHORT_MIN_SIZE)
 }
 
+/// Creates a post. Honours the `Idempotency-Key` header.
 #[post("/posts/")]
+#[allow(clippy::too_many_arguments)]
 pub async fn create(
+	mongo_client: web::Data<MongoClient>,
 	db: web::Data<Database>,
 	masking_key: web::Data<&'static MaskingKey>,
+	content_filter: web::Data<&'static ContentFilter>,
+	crisis_detector: web::Data<&'static CrisisDetector>,
 	user: AuthenticatedUser,
+	req: HttpRequest,
 	request: web::Json<CreateRequest>,
-) -> ApiResult<Created, ()> {
+) -> HttpResponse {
+	idempotency::respond(
+		&db,
+		&req,
+		user.id,
+		&*request,
+		create_post(
+			&mongo_client,
+			&db,
+			&masking_key,
+			&content_filter,
+			&crisis_detector,
+			&user,
+			&request,
+		),
+	)
+	.await
+}
+
+async fn create_post(
+	mongo_client: &MongoClient,
+	db: &Database,
+	masking_key: &MaskingKey,
+	content_filter: &ContentFilter,
+	crisis_detector: &CrisisDetector,
+	user: &AuthenticatedUser,
+	request: &CreateRequest,
+) -> ApiResult<Created, CreateError> {
 	if request.text.len() > conf::POST_MAX_SIZE {
 		return Err(Failure::BadRequest("oversized post text"));
 	}
 
+	if request.attachments.len() > conf::POST_MAX_ATTACHMENTS {
+		return Err(Failure::BadRequest("too many attachments"));
+	}
+
+	if request.attachments.iter().any(|attachment| {
+		attachment.alt_text.trim().is_empty()
+			|| attachment.alt_text.len() > conf::ATTACHMENT_ALT_TEXT_MAX_SIZE
+	}) {
+		return Err(Failure::BadRequest("missing or oversized alt text"));
+	}
+
+	let poll_closes_at = match &request.poll {
+		Some(poll) => {
+			if !conf::POLL_OPTIONS.contains(&poll.options.len()) {
+				return Err(Failure::BadRequest("wrong number of poll options"));
+			}
+
+			if poll
+				.options
+				.iter()
+				.any(|option| option.trim().is_empty() || option.len() > conf::POLL_OPTION_MAX_SIZE)
+			{
+				return Err(Failure::BadRequest("empty or oversized poll option"));
+			}
+
+			if poll.options.iter().collect::<HashSet<&String>>().len() != poll.options.len() {
+				return Err(Failure::BadRequest("repeated poll option"));
+			}
+
+			poll.closes_at
+				.as_ref()
+				.map(|closes_at| {
+					let closes_at = DateTime::parse_rfc3339_str(closes_at)
+						.map_err(|_| Failure::BadRequest("bad poll closing time"))?;
+					let now = DateTime::now().timestamp_millis();
+					let latest = now + i64::try_from(conf::POLL_MAX_DURATION.as_millis()).unwrap();
+					if closes_at.timestamp_millis() <= now || closes_at.timestamp_millis() > latest
+					{
+						return Err(Failure::BadRequest("poll closing time out of range"));
+					}
+					Ok(closes_at)
+				})
+				.transpose()?
+		}
+		None => None,
+	};
+
+	let rejected = |Rejected(category)| Failure::Expected(CreateError::ContentRejected(category));
+	let screened = content_filter.screen(&request.text).map_err(rejected)?;
+	let crisis_flagged = !crisis_detector.detect(&screened.text).is_empty();
+	let mut held = screened.held;
+
+	let mut attachments = Vec::with_capacity(request.attachments.len());
+	if !request.attachments.is_empty() {
+		let mut uploads = db
+			.collection::<Upload>("uploads")
+			.find(
+				doc! {
+					"owner": {"$eq": user.id},
+					"name": {"$in": request.attachments.iter().map(|attachment| &attachment.id).collect::<Vec<&String>>()},
+					"created_at": {"$gte": attachments::upload_cutoff()},
+				},
+				None,
+			)
+			.await
+			.map_err(to_unexpected!("Getting uploads cursor failed"))?
+			.map_ok(|upload| (upload.name.clone(), upload))
+			.try_collect::<HashMap<String, Upload>>()
+			.await
+			.map_err(to_unexpected!("Getting uploads failed"))?;
+
+		for attachment in &request.attachments {
+			let upload = uploads
+				.remove(&attachment.id)
+				.ok_or(Failure::BadRequest("unknown or repeated attachment id"))?;
+			let alt_text = content_filter
+				.screen(&attachment.alt_text)
+				.map_err(rejected)?;
+			held.extend(alt_text.held);
+
+			attachments.push(Attachment {
+				name: upload.name,
+				width: upload.width,
+				height: upload.height,
+				alt_text: alt_text.text,
+			});
+		}
+	}
+
+	let poll = match &request.poll {
+		Some(poll) => {
+			let mut options = Vec::with_capacity(poll.options.len());
+			for option in &poll.options {
+				let option = content_filter.screen(option).map_err(rejected)?;
+				held.extend(option.held);
+				options.push(PollOption {
+					text: option.text,
+					votes: 0,
+				});
+			}
+
+			Some(Poll {
+				options,
+				closes_at: poll_closes_at,
+			})
+		}
+		None => None,
+	};
+
+	held.sort_by_key(|category| *category as u8);
+	held.dedup();
+
+	let status = if held.is_empty() {
+		PostStatus::Visible
+	} else {
+		PostStatus::Held
+	};
+
+	let owner = db
+		.collection::<User>("users")
+		.find_one(doc! {"_id": {"$eq": user.id}}, None)
+		.await
+		.map_err(to_unexpected!("Finding post owner failed"))?
+		.ok_or(Failure::BadRequest("no account matches this id"))?;
+
+	if !owner.is_verified() {
+		let schools = db.collection::<School>("schools");
+		let school = schools
+			.find_one(doc! {"_id": {"$eq": &owner.school_id}}, None)
+			.await
+			.map_err(to_unexpected!("Finding poster's school failed"))?;
+
+		// campuses require verification if their parent does
+		let verification_required = match school {
+			Some(School {
+				verification_required: true,
+				..
+			}) => true,
+			Some(School {
+				parent_id: Some(parent_id),
+				..
+			}) => {
+				schools
+					.count_documents(
+						doc! {
+							"_id": {"$eq": parent_id},
+							"verification_required": true,
+						},
+						None,
+					)
+					.await
+					.map_err(to_unexpected!(
+						"Checking parent school verification requirement failed"
+					))? != 0
+			}
+			_ => false,
+		};
+
+		if verification_required {
+			return Err(Failure::Expected(CreateError::VerificationRequired));
+		}
+	}
+
+	let (year_of_study, faculty) = disclosed_attributes(db, &owner).await?;
+	let verified = disclosed_verification(db, &owner).await?;
+	let school_id = owner.school_id;
+
 	let mut insert_doc = doc! {
 		"owner": &user.id,
-		"text": &request.text,
+		"school_id": &school_id,
+		"year_of_study": to_bson(&year_of_study).map_err(to_unexpected!("Converting year of study to bson failed"))?,
+		"faculty": to_bson(&faculty).map_err(to_unexpected!("Converting faculty to bson failed"))?,
+		"status": status,
+		"crisis_flagged": crisis_flagged,
+		"verified": verified,
+		"text": &screened.text,
+		"attachments": to_bson(&attachments).map_err(to_unexpected!("Converting attachments to bson failed"))?,
+		"poll": to_bson(&poll).map_err(to_unexpected!("Converting poll to bson failed"))?,
 		"votes_up": 0,
 		"votes_down": 0,
 		"absolute_score": 0,
-		"trending_score": get_trending_score_time(&DateTime::now()),  // approximate, but will match `_id` exactly with the next vote
 	};
+	let now = DateTime::now();
+	for ranking in ranking::ALL {
+		// approximate for time-based scores, but will match `_id` exactly with the next vote
+		insert_doc.insert(ranking.score_field(), ranking.score(0, 0, &now));
+	}
+	let mut session = mongo_client
+		.start_session(None)
+		.await
+		.map_err(to_unexpected!("Starting session failed"))?;
+
+	// the post and its owner's post count are written together
 	let mut attempt = 0;
-	let insertion = loop {
+	let post_id = loop {
 		attempt += 1;
 		if attempt > 100 {
 			error!("Too many post creation attempts");
@@ -258,46 +1131,204 @@ pub async fn create(
 		let new_sequential_id = last_sequential_id.unwrap_or(0) + 1;
 		insert_doc.insert("sequential_id", new_sequential_id);
 
-		match db
-			.collection::<Document>("posts")
-			.insert_one(&insert_doc, None)
+		session
+			.start_transaction(None)
+			.await
+			.map_err(to_unexpected!("Starting transaction failed"))?;
+
+		let insertion = match db
+			.collection::<Document>("posts")
+			.insert_one_with_session(&insert_doc, None, &mut session)
 			.await
 		{
-			Ok(insertion) => break insertion,
+			Ok(insertion) => insertion,
 			Err(err) => match err.kind.as_ref() {
 				ErrorKind::Write(WriteFailure::WriteError(write_err))
 					if write_err.code == 11000 =>
 				{
 					info!("Retrying post creation: {}", err);
+					session
+						.abort_transaction()
+						.await
+						.map_err(to_unexpected!("Aborting post creation transaction failed"))?;
+					continue;
 				}
 				_ => {
 					error!("Creating post failed: {}", err);
 					return Err(Failure::Unexpected);
 				}
 			},
+		};
+
+		db.collection::<User>("users")
+			.update_one_with_session(
+				doc! {"_id": {"$eq": user.id}},
+				doc! {"$inc": {"post_count": 1_i64}},
+				None,
+				&mut session,
+			)
+			.await
+			.map_err(to_unexpected!("Counting post failed"))?;
+
+		match session.commit_transaction().await {
+			Ok(()) => break insertion.inserted_id.as_object_id().unwrap(),
+			Err(err) => info!("Retrying post creation: {}", err),
+		}
+	};
+
+	let mut review_reasons = held
+		.into_iter()
+		.map(ReviewReason::ContentFilter)
+		.collect::<Vec<ReviewReason>>();
+	if crisis_flagged {
+		review_reasons.push(ReviewReason::CrisisLanguage);
+	}
+
+	if !review_reasons.is_empty() {
+		// held posts remain findable by their status if this fails
+		if let Err(err) = db
+			.collection::<Review>("reviews")
+			.insert_one(
+				Review {
+					post: post_id,
+					reasons: review_reasons,
+					urgent: crisis_flagged,
+					created_at: DateTime::now(),
+				},
+				None,
+			)
+			.await
+		{
+			error!("Queueing post for review failed: {}", err);
 		}
+	}
+
+	let support = if crisis_flagged {
+		let counselling = db
+			.collection::<School>("schools")
+			.find_one(doc! {"_id": {"$eq": &school_id}}, None)
+			.await
+			.map_err(to_unexpected!("Finding school counselling failed"))?
+			.and_then(|school| school.counselling);
+
+		Some(SupportResources {
+			school: counselling,
+			crisis_lines: crisis_detector.crisis_lines.clone(),
+		})
+	} else {
+		None
 	};
 
 	success(Created {
-		id: masking_key.mask(&insertion.inserted_id.as_object_id().unwrap()),
+		id: masking_key.mask(&post_id),
+		pending_review: status == PostStatus::Held,
+		support,
 	})
 }
 
+#[derive(Deserialize)]
+pub struct CursorQuery {
+	/// The `cursor` of the last item of the previous page.
+	pub before: Option<MaskedObjectId>,
+}
+
+#[derive(Serialize)]
+pub struct Saved {
+	/// Identifies the save for pagination.
+	pub cursor: MaskedObjectId,
+	pub saved_at: String,
+	pub post_id: MaskedObjectId,
+	/// Absent if the post has since been removed.
+	pub post: Option<Detail>,
+}
+
+#[derive(Deserialize)]
+pub struct OwnPostsQuery {
+	pub before: Option<MaskedSequentialId>,
+}
+
+#[derive(Serialize)]
+pub struct OwnPost {
+	#[serde(flatten)]
+	pub post: Detail,
+	pub status: PostStatus,
+}
+
+#[derive(Serialize)]
+pub struct Voted {
+	/// Identifies the vote for pagination.
+	pub cursor: MaskedObjectId,
+	pub value: i32,
+	pub voted_at: String,
+	pub post_id: MaskedObjectId,
+	/// Absent if the post has since been removed.
+	pub post: Option<Detail>,
+}
+
+#[derive(Deserialize)]
+struct PostOwner {
+	owner: ObjectId,
+}
+
+/// A vote, as stored.
+#[derive(Deserialize)]
+struct CastVote {
+	#[serde(rename = "_id")]
+	id: ObjectId,
+	post: ObjectId,
+	value: i32,
+	updated_at: DateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub enum PollVoteError {
+	/// The poll no longer accepts votes.
+	Closed,
+}
+
+impl ApiError for PollVoteError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::Closed => StatusCode::CONFLICT,
+		}
+	}
+}
+
+/// Votes on a post. Honours the `Idempotency-Key` header.
 #[put("/posts/{post_id}/vote")]
 pub async fn vote(
 	mongo_client: web::Data<MongoClient>,
 	db: web::Data<Database>,
 	masking_key: web::Data<&'static MaskingKey>,
 	user: AuthenticatedUser,
+	req: HttpRequest,
 	post_id: web::Path<MaskedObjectId>,
 	request: web::Json<i32>, // TODO: enum; see https://github.com/serde-rs/serde/issues/745
+) -> HttpResponse {
+	idempotency::respond(
+		&db,
+		&req,
+		user.id,
+		&*request,
+		vote_post(&mongo_client, &db, &masking_key, &user, &post_id, *request),
+	)
+	.await
+}
+
+async fn vote_post(
+	mongo_client: &MongoClient,
+	db: &Database,
+	masking_key: &MaskingKey,
+	user: &AuthenticatedUser,
+	post_id: &MaskedObjectId,
+	request: i32,
 ) -> ApiResult<Votes, ()> {
-	if !(-1..=1).contains(&*request) {
+	if !(-1..=1).contains(&request) {
 		return Err(Failure::BadRequest("invalid vote"));
 	}
 
 	let post_id = masking_key
-		.unmask(&post_id)
+		.unmask(post_id)
 		.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked id"))?;
 
 	let mut session = mongo_client
@@ -332,7 +1363,7 @@ pub async fn vote(
 			)
 			.await
 			.map_err(to_unexpected!("Finding existing vote failed"))?
-			.map(|v| v.value);
+			.map(|v| (v.value, v.updated_at));
 
 		session
 			.start_transaction(None)
@@ -347,7 +1378,8 @@ pub async fn vote(
 						Vote {
 							post: post_id,
 							user: user.id,
-							value: *request,
+							value: request,
+							updated_at: DateTime::now(),
 						},
 						None,
 						&mut session,
@@ -361,18 +1393,22 @@ pub async fn vote(
 					}
 				}
 			}
-			Some(existing_vote) => {
+			Some((existing_value, existing_updated_at)) => {
 				match db
 					.collection::<Vote>("votes")
 					.update_one_with_session(
 						doc! {
 							"post": {"$eq": post_id},
 							"user": {"$eq": user.id},
-							"value": {"$eq": existing_vote},
+							"value": {"$eq": existing_value},
+							"updated_at": {"$eq": existing_updated_at},
 						},
 						doc! {
 							"$set": {
-								"value": *request,
+								"value": request,
+							},
+							"$currentDate": {
+								"updated_at": true,
 							},
 						},
 						None,
@@ -393,17 +1429,27 @@ pub async fn vote(
 			}
 		}
 
-		let votes_up_difference = -i32::from(existing_vote == Some(1)) + i32::from(*request == 1);
+		let existing_value = existing_vote.map(|(value, _)| value);
+		let votes_up_difference = -i32::from(existing_value == Some(1)) + i32::from(request == 1);
 		let votes_down_difference =
-			-i32::from(existing_vote == Some(-1)) + i32::from(*request == -1);
-		let difference = -existing_vote.unwrap_or(0) + *request;
+			-i32::from(existing_value == Some(-1)) + i32::from(request == -1);
+		let change = VoteChange::new(existing_vote, request, DateTime::now());
+		let difference = change.difference;
 
-		let trending_score_time = get_trending_score_time(&post_id.timestamp());
+		let scores = ranking::ALL
+			.iter()
+			.map(|ranking| {
+				(
+					ranking.score_field().to_owned(),
+					ranking.vote_update(&change),
+				)
+			})
+			.collect::<Document>();
 
 		// TODO: Are update pipelines atomic? I haven’t found a straight answer yet.
-		let post_update = db
-			.collection::<Post>("posts")
-			.update_one_with_session(
+		let post_owner = db
+			.collection::<PostOwner>("posts")
+			.find_one_and_update_with_session(
 				doc! {
 					"_id": {"$eq": post_id},
 				},
@@ -422,32 +1468,38 @@ pub async fn vote(
 						},
 					},
 					doc! {
-						"$addFields": {
-							"trending_score": {"$add": [
-								{"$multiply": [
-									{"$cond": [
-										{"$lt": ["$absolute_score", 0]},
-										-1,
-										1,
-									]},
-									{"$ln":
-										{"$add": [1, {"$abs": "$absolute_score"}]}},
-								]},
-								{"$literal": trending_score_time},
-							]},
-						},
+						"$addFields": scores,
 					},
 				],
-				None,
+				FindOneAndUpdateOptions::builder()
+					.projection(doc! {"_id": false, "owner": true})
+					.build(),
 				&mut session,
 			)
 			.await
-			.map_err(to_unexpected!("Updating post score failed"))?;
+			.map_err(to_unexpected!("Updating post score failed"))?
+			.ok_or_else(|| {
+				error!("Updating post score failed: no such post");
+				Failure::Unexpected
+			})?
+			.owner;
 
-		if post_update.matched_count != 1 {
-			error!("Updating post score failed: no such post");
-			return Err(Failure::Unexpected);
-		}
+		db.collection::<User>("users")
+			.update_one_with_session(
+				doc! {
+					"_id": {"$eq": post_owner},
+				},
+				doc! {
+					"$inc": {
+						"upvotes_received": i64::from(votes_up_difference),
+						"karma": i64::from(difference),
+					},
+				},
+				None,
+				&mut session,
+			)
+			.await
+			.map_err(to_unexpected!("Updating post owner karma failed"))?;
 
 		if let Err(err) = session.commit_transaction().await {
 			debug!("Committing voting transaction failed: {}", err);
@@ -480,3 +1532,540 @@ pub async fn vote(
 
 	success(votes)
 }
+
+/// Chooses an option in the poll of a post, by its index. The choice can be changed until the poll closes.
+#[put("/posts/{post_id}/poll")]
+pub async fn vote_poll(
+	mongo_client: web::Data<MongoClient>,
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	user: AuthenticatedUser,
+	post_id: web::Path<MaskedObjectId>,
+	request: web::Json<u32>,
+) -> ApiResult<PollDetail, PollVoteError> {
+	let post_id = masking_key
+		.unmask(&post_id)
+		.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked id"))?;
+	let option = *request;
+
+	let find_poll = || async {
+		db.collection::<Post>("posts")
+			.find_one(
+				doc! {
+					"_id": {"$eq": post_id},
+					"status": PostStatus::Visible,
+				},
+				None,
+			)
+			.await
+			.map_err(to_unexpected!("Finding poll failed"))?
+			.ok_or(Failure::BadRequest("no post found for this id"))?
+			.poll
+			.ok_or(Failure::BadRequest("post has no poll"))
+	};
+
+	let poll = find_poll().await?;
+
+	if usize::try_from(option).unwrap() >= poll.options.len() {
+		return Err(Failure::BadRequest("invalid poll option"));
+	}
+
+	if poll.is_closed() {
+		return Err(Failure::Expected(PollVoteError::Closed));
+	}
+
+	let mut session = mongo_client
+		.start_session(None)
+		.await
+		.map_err(to_unexpected!("Starting session failed"))?;
+
+	let mut attempt = 0;
+	'atomic_vote: loop {
+		attempt += 1;
+		if attempt > 8 {
+			error!("Too many poll voting attempts");
+			return Err(Failure::Unexpected);
+		}
+
+		if attempt > 1 {
+			session
+				.abort_transaction()
+				.await
+				.map_err(to_unexpected!("Aborting poll vote transaction failed"))?;
+		}
+
+		let existing_vote = db
+			.collection::<PollVote>("poll_votes")
+			.find_one_with_session(
+				doc! {
+					"post": {"$eq": post_id},
+					"user": {"$eq": user.id},
+				},
+				None,
+				&mut session,
+			)
+			.await
+			.map_err(to_unexpected!("Finding existing poll vote failed"))?
+			.map(|v| v.option);
+
+		if existing_vote == Some(option) {
+			break;
+		}
+
+		session
+			.start_transaction(None)
+			.await
+			.map_err(to_unexpected!("Starting transaction failed"))?;
+
+		match existing_vote {
+			None => {
+				match db
+					.collection::<PollVote>("poll_votes")
+					.insert_one_with_session(
+						PollVote {
+							post: post_id,
+							user: user.id,
+							option,
+							updated_at: DateTime::now(),
+						},
+						None,
+						&mut session,
+					)
+					.await
+				{
+					Ok(_) => {}
+					Err(err) => {
+						debug!("Inserting poll vote failed: {}", err);
+						continue 'atomic_vote;
+					}
+				}
+			}
+			Some(existing_vote) => {
+				match db
+					.collection::<PollVote>("poll_votes")
+					.update_one_with_session(
+						doc! {
+							"post": {"$eq": post_id},
+							"user": {"$eq": user.id},
+							"option": {"$eq": existing_vote},
+						},
+						doc! {
+							"$set": {
+								"option": option,
+							},
+							"$currentDate": {
+								"updated_at": true,
+							},
+						},
+						None,
+						&mut session,
+					)
+					.await
+				{
+					Ok(update_result) if update_result.matched_count == 1 => {}
+					Ok(_) => {
+						debug!("Updating poll vote failed: no match");
+						continue 'atomic_vote;
+					}
+					Err(err) => {
+						debug!("Updating poll vote failed: {}", err);
+						continue 'atomic_vote;
+					}
+				}
+			}
+		}
+
+		let mut tally = doc! {format!("poll.options.{}.votes", option): 1};
+		if let Some(existing_vote) = existing_vote {
+			tally.insert(format!("poll.options.{}.votes", existing_vote), -1);
+		}
+
+		let post_update = db
+			.collection::<Post>("posts")
+			.update_one_with_session(
+				doc! {
+					"_id": {"$eq": post_id},
+					"$or": [
+						{"poll.closes_at": {"$eq": null}},
+						{"poll.closes_at": {"$gt": DateTime::now()}},
+					],
+				},
+				doc! {"$inc": tally},
+				None,
+				&mut session,
+			)
+			.await
+			.map_err(to_unexpected!("Updating poll tally failed"))?;
+
+		if post_update.matched_count != 1 {
+			// closed since it was checked
+			session
+				.abort_transaction()
+				.await
+				.map_err(to_unexpected!("Aborting poll vote transaction failed"))?;
+			return Err(Failure::Expected(PollVoteError::Closed));
+		}
+
+		if let Err(err) = session.commit_transaction().await {
+			debug!("Committing poll voting transaction failed: {}", err);
+			continue 'atomic_vote;
+		}
+
+		break;
+	}
+
+	success(PollDetail::from_poll(find_poll().await?, Some(option))?)
+}
+
+/// Saves a post for the user to read later. Saving a post again keeps its original place in the list.
+#[put("/posts/{post_id}/save")]
+pub async fn save_post(
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	user: AuthenticatedUser,
+	post_id: web::Path<MaskedObjectId>,
+) -> ApiResult<(), ()> {
+	let post_id = masking_key
+		.unmask(&post_id)
+		.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked id"))?;
+
+	db.collection::<Post>("posts")
+		.find_one(
+			doc! {
+				"_id": {"$eq": post_id},
+				"status": PostStatus::Visible,
+			},
+			FindOneOptions::builder()
+				.projection(doc! {"_id": true})
+				.build(),
+		)
+		.await
+		.map_err(to_unexpected!("Finding post to save failed"))?
+		.ok_or(Failure::BadRequest("no post found for this id"))?;
+
+	db.collection::<Save>("saves")
+		.update_one(
+			doc! {
+				"user": {"$eq": user.id},
+				"post": {"$eq": post_id},
+			},
+			doc! {
+				"$setOnInsert": {
+					"user": user.id,
+					"post": post_id,
+				},
+			},
+			UpdateOptions::builder().upsert(true).build(),
+		)
+		.await
+		.map_err(to_unexpected!("Saving post failed"))?;
+
+	success(())
+}
+
+/// Hides a post from the user's feeds.
+#[post("/posts/{post_id}/hide")]
+pub async fn hide_post(
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	user: AuthenticatedUser,
+	post_id: web::Path<MaskedObjectId>,
+) -> ApiResult<(), ()> {
+	let post_id = masking_key
+		.unmask(&post_id)
+		.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked id"))?;
+
+	db.collection::<Post>("posts")
+		.find_one(
+			doc! {"_id": {"$eq": post_id}},
+			FindOneOptions::builder()
+				.projection(doc! {"_id": true})
+				.build(),
+		)
+		.await
+		.map_err(to_unexpected!("Finding post to hide failed"))?
+		.ok_or(Failure::BadRequest("no post found for this id"))?;
+
+	db.collection::<Hide>("hides")
+		.update_one(
+			doc! {
+				"user": {"$eq": user.id},
+				"post": {"$eq": post_id},
+			},
+			doc! {
+				"$setOnInsert": {
+					"user": user.id,
+					"post": post_id,
+				},
+			},
+			UpdateOptions::builder().upsert(true).build(),
+		)
+		.await
+		.map_err(to_unexpected!("Hiding post failed"))?;
+
+	success(())
+}
+
+/// Removes a post from the user's saved posts, if it's there.
+#[delete("/posts/{post_id}/save")]
+pub async fn unsave_post(
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	user: AuthenticatedUser,
+	post_id: web::Path<MaskedObjectId>,
+) -> ApiResult<(), ()> {
+	let post_id = masking_key
+		.unmask(&post_id)
+		.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked id"))?;
+
+	db.collection::<Save>("saves")
+		.delete_one(
+			doc! {
+				"user": {"$eq": user.id},
+				"post": {"$eq": post_id},
+			},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Unsaving post failed"))?;
+
+	success(())
+}
+
+/// Gets the [`Detail`]s of the given posts in order, for the given user. Posts that aren't visible are `None`.
+async fn visible_details(
+	db: &Database,
+	masking_key: &MaskingKey,
+	crisis_detector: &CrisisDetector,
+	viewer: &AuthenticatedUser,
+	post_ids: &[ObjectId],
+) -> Result<Vec<Option<Detail>>, Failure<()>> {
+	let mut posts = db
+		.collection::<Post>("posts")
+		.find(
+			doc! {
+				"_id": {"$in": post_ids},
+				"status": PostStatus::Visible,
+			},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Getting posts cursor failed"))?
+		.map_ok(|post| (post.id, post))
+		.try_collect::<HashMap<ObjectId, Post>>()
+		.await
+		.map_err(to_unexpected!("Getting posts failed"))?;
+
+	let present = post_ids
+		.iter()
+		.map(|post_id| posts.contains_key(post_id))
+		.collect::<Vec<bool>>();
+	let visible_posts = post_ids
+		.iter()
+		.filter_map(|post_id| posts.remove(post_id))
+		.collect::<Vec<Post>>();
+	let mut details = details(
+		db,
+		masking_key,
+		crisis_detector,
+		Some(viewer),
+		visible_posts,
+	)
+	.await?
+	.into_iter();
+
+	Ok(present
+		.into_iter()
+		.map(|present| if present { details.next() } else { None })
+		.collect())
+}
+
+/// Lists the user's saved posts, most recently saved first.
+///
+/// Posts that have been removed since they were saved are listed without their details.
+#[get("/users/saved/")]
+pub async fn list_saved(
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	crisis_detector: web::Data<&'static CrisisDetector>,
+	user: AuthenticatedUser,
+	query: web::Query<CursorQuery>,
+) -> ApiResult<Box<[Saved]>, ()> {
+	let mut filter = doc! {"user": {"$eq": user.id}};
+
+	if let Some(before) = &query.before {
+		let before = masking_key
+			.unmask(before)
+			.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked id"))?;
+		filter.insert("_id", doc! {"$lt": before});
+	}
+
+	let saves = db
+		.collection::<Save>("saves")
+		.find(
+			filter,
+			FindOptions::builder()
+				.sort(doc! {"_id": -1})
+				.limit(i64::from(conf::POSTS_PAGE_SIZE))
+				.build(),
+		)
+		.await
+		.map_err(to_unexpected!("Getting saves cursor failed"))?
+		.try_collect::<Vec<Save>>()
+		.await
+		.map_err(to_unexpected!("Getting saves failed"))?;
+
+	let posts = visible_details(
+		&db,
+		&masking_key,
+		&crisis_detector,
+		&user,
+		&saves
+			.iter()
+			.map(|save| save.post)
+			.collect::<Vec<ObjectId>>(),
+	)
+	.await?;
+
+	let saved = saves
+		.into_iter()
+		.zip(posts)
+		.map(|(save, post)| {
+			Ok(Saved {
+				cursor: masking_key.mask(&save.id),
+				saved_at: save
+					.id
+					.timestamp()
+					.try_to_rfc3339_string()
+					.map_err(to_unexpected!("Formatting save timestamp failed"))?,
+				post_id: masking_key.mask(&save.post),
+				post,
+			})
+		})
+		.collect::<Result<Vec<Saved>, Failure<()>>>()?;
+
+	success(saved.into())
+}
+
+/// Lists the user's own posts, newest first, including those held for review or hidden by moderators.
+#[get("/users/posts/")]
+pub async fn list_own_posts(
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	crisis_detector: web::Data<&'static CrisisDetector>,
+	user: AuthenticatedUser,
+	query: web::Query<OwnPostsQuery>,
+) -> ApiResult<Box<[OwnPost]>, ()> {
+	let mut filter = doc! {
+		"owner": {"$eq": user.id},
+		"status": {"$ne": PostStatus::Deleted},
+	};
+
+	if let Some(before) = &query.before {
+		let before = masking_key
+			.unmask_sequential(before)
+			.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked sequential id"))?;
+		filter.insert(
+			"sequential_id",
+			doc! {"$lt": i64::try_from(before).unwrap()},
+		);
+	}
+
+	let posts = db
+		.collection::<Post>("posts")
+		.find(
+			filter,
+			FindOptions::builder()
+				.sort(doc! {"sequential_id": -1})
+				.limit(i64::from(conf::POSTS_PAGE_SIZE))
+				.build(),
+		)
+		.await
+		.map_err(to_unexpected!("Getting own posts cursor failed"))?
+		.try_collect::<Vec<Post>>()
+		.await
+		.map_err(to_unexpected!("Getting own posts failed"))?;
+
+	let statuses = posts
+		.iter()
+		.map(|post| post.status)
+		.collect::<Vec<PostStatus>>();
+	let own_posts = details(&db, &masking_key, &crisis_detector, Some(&user), posts)
+		.await?
+		.into_iter()
+		.zip(statuses)
+		.map(|(post, status)| OwnPost { post, status })
+		.collect::<Vec<OwnPost>>();
+
+	success(own_posts.into())
+}
+
+/// Lists the posts the user has voted on, in the order they first voted on them, most recent first.
+///
+/// Posts that have been removed since are listed without their details.
+#[get("/users/votes/")]
+pub async fn list_own_votes(
+	db: web::Data<Database>,
+	masking_key: web::Data<&'static MaskingKey>,
+	crisis_detector: web::Data<&'static CrisisDetector>,
+	user: AuthenticatedUser,
+	query: web::Query<CursorQuery>,
+) -> ApiResult<Box<[Voted]>, ()> {
+	let mut filter = doc! {
+		"user": {"$eq": user.id},
+		"value": {"$ne": 0},
+	};
+
+	if let Some(before) = &query.before {
+		let before = masking_key
+			.unmask(before)
+			.map_err(|masked_oid::PaddingError| Failure::BadRequest("bad masked id"))?;
+		filter.insert("_id", doc! {"$lt": before});
+	}
+
+	let votes = db
+		.collection::<CastVote>("votes")
+		.find(
+			filter,
+			FindOptions::builder()
+				.sort(doc! {"_id": -1})
+				.limit(i64::from(conf::POSTS_PAGE_SIZE))
+				.build(),
+		)
+		.await
+		.map_err(to_unexpected!("Getting own votes cursor failed"))?
+		.try_collect::<Vec<CastVote>>()
+		.await
+		.map_err(to_unexpected!("Getting own votes failed"))?;
+
+	let posts = visible_details(
+		&db,
+		&masking_key,
+		&crisis_detector,
+		&user,
+		&votes
+			.iter()
+			.map(|cast_vote| cast_vote.post)
+			.collect::<Vec<ObjectId>>(),
+	)
+	.await?;
+
+	let voted = votes
+		.into_iter()
+		.zip(posts)
+		.map(|(cast_vote, post)| {
+			Ok(Voted {
+				cursor: masking_key.mask(&cast_vote.id),
+				value: cast_vote.value,
+				voted_at: cast_vote
+					.updated_at
+					.try_to_rfc3339_string()
+					.map_err(to_unexpected!("Formatting vote timestamp failed"))?,
+				post_id: masking_key.mask(&cast_vote.post),
+				post,
+			})
+		})
+		.collect::<Result<Vec<Voted>, Failure<()>>>()?;
+
+	success(voted.into())
+}
@@ -1,11 +1,19 @@
+use std::collections::{
+	HashMap,
+	HashSet,
+};
+use std::time::SystemTime;
+
+use actix_web::http::StatusCode;
 use actix_web::{
 	delete,
 	get,
+	patch,
 	post,
 	put,
 	web,
 };
-use futures::StreamExt;
+use futures::TryStreamExt;
 use log::error;
 use mongodb::bson::{
 	doc,
@@ -17,16 +25,26 @@ use serde::{
 	Deserialize,
 	Serialize,
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
 	api_types::{
+		failure,
 		success,
+		ApiError,
 		ApiResult,
 		Failure,
 	},
 	auth::AuthenticatedUser,
+	conf,
+	content_filter::{
+		Category,
+		ContentFilter,
+		Rejected,
+	},
 	to_unexpected,
 	types::{
+		Patch,
 		PosterFaculty,
 		PosterYearOfStudy,
 		School,
@@ -44,6 +62,38 @@ pub struct UpdatableProfileData {
 	pub school_id: Option<String>,
 }
 
+/// Changes to a user's profile. Absent fields are left unchanged.
+#[derive(Deserialize)]
+pub struct ProfilePatch {
+	// `null` to keep it private.
+	#[serde(default)]
+	pub year_of_study: Patch<PosterYearOfStudy>,
+	// `null` to keep it private.
+	#[serde(default)]
+	pub faculty: Patch<PosterFaculty>,
+	// Can't be `null`.
+	#[serde(default)]
+	pub school_id: Patch<String>,
+	#[serde(default)]
+	pub display_name: Patch<String>,
+	#[serde(default)]
+	pub bio: Patch<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum ProfileError {
+	/// The display name or bio was refused by a content filter rule of the given category.
+	ContentRejected(Category),
+}
+
+impl ApiError for ProfileError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::ContentRejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+		}
+	}
+}
+
 #[derive(Serialize)]
 pub struct ProfileData {
 	// Year of study of the poster.
@@ -54,6 +104,15 @@ pub struct ProfileData {
 	pub school_id: String,
 	// Username of user
 	pub username: String,
+	pub post_count: i64,
+	pub upvotes_received: i64,
+	pub karma: i64,
+	pub created_at: String,
+	pub account_age_days: u64,
+	pub display_name: Option<String>,
+	pub bio: Option<String>,
+	/// Whether the user verified they belong to their school, with a school email address.
+	pub verified: bool,
 }
 
 /// Fetches user profile information.
@@ -71,20 +130,68 @@ pub async fn get_profile(
 	match user {
 		Ok(possible_user) => match possible_user {
 			Some(user) => {
-				return success(ProfileData {
+				let created_at = user.id.timestamp();
+				let verified = user.is_verified();
+				success(ProfileData {
 					year_of_study: user.year_of_study,
 					faculty: user.faculty,
 					school_id: user.school_id,
 					username: user.username.into(),
+					post_count: user.post_count,
+					upvotes_received: user.upvotes_received,
+					karma: user.karma,
+					created_at: created_at
+						.try_to_rfc3339_string()
+						.map_err(to_unexpected!("Formatting account creation time failed"))?,
+					account_age_days: SystemTime::now()
+						.duration_since(created_at.to_system_time())
+						.map_or(0, |age| age.as_secs() / (3600 * 24)),
+					display_name: user.display_name,
+					bio: user.bio,
+					verified,
 				})
 			}
-			None => return Err(Failure::BadRequest("no account matches this id")),
+			None => Err(Failure::BadRequest("no account matches this id")),
 		},
 		Err(err) => {
 			error!("Fetching user information failed: {}", err);
-			return Err(Failure::Unexpected);
+			Err(Failure::Unexpected)
 		}
-	};
+	}
+}
+
+/// Checks that a school exists and can be chosen by the user. Retired schools can only be kept, not newly chosen.
+async fn validate_school_id<E: ApiError>(
+	db: &Database,
+	user: &AuthenticatedUser,
+	school_id: &str,
+) -> Result<(), Failure<E>> {
+	let school = db
+		.collection::<School>("schools")
+		.find_one(doc! {"_id": {"$eq": school_id}}, None)
+		.await
+		.map_err(to_unexpected!("validating school's existence failed"))?
+		.ok_or(Failure::BadRequest("invalid school id"))?;
+
+	if school.retired {
+		let current = db
+			.collection::<User>("users")
+			.count_documents(
+				doc! {
+					"_id": {"$eq": user.id},
+					"school_id": {"$eq": school_id},
+				},
+				None,
+			)
+			.await
+			.map_err(to_unexpected!("Finding user's school failed"))?;
+
+		if current == 0 {
+			return Err(Failure::BadRequest("school is retired"));
+		}
+	}
+
+	Ok(())
 }
 
 /// Updates user profile information.
@@ -94,8 +201,7 @@ pub async fn get_profile(
 ///
 /// [`school_id`] is optional when updating, and will only be changed if a valid [`school_id`] is passed.
 ///
-/// Not a PATCH request because couldn't do this: https://stackoverflow.com/questions/44331037/how-can-i-distinguish-between-a-deserialized-field-that-is-missing-and-one-that
-/// because Actix-web's [`web::Json`] inferes both `undefined` and `null` as `None`, making PATCH requests difficult.
+/// Since absent fields are cleared, [`patch_profile`] should be preferred.
 #[put("/users/profile/")]
 pub async fn update_profile(
 	db: web::Data<Database>,
@@ -122,11 +228,7 @@ pub async fn update_profile(
 	if let Some(school_id) = &update_data.school_id {
 		// Check to see if a user's new proposed [`school_id`] is valid (and exists), before adding
 		// it to the [`update_doc`].
-		db.collection::<School>("schools")
-			.find_one(doc! {"_id": {"$eq": school_id}}, None)
-			.await
-			.map_err(to_unexpected!("validating school's existence failed"))?
-			.ok_or(Failure::BadRequest("invalid school id"))?;
+		validate_school_id(&db, &user, school_id).await?;
 		update_doc.insert("school_id", school_id);
 	}
 
@@ -142,14 +244,113 @@ pub async fn update_profile(
 		.await;
 	match user {
 		Ok(possible_user) => match possible_user {
-			Some(_) => return success(()),
-			None => return Err(Failure::BadRequest("no account matches this id")),
+			Some(_) => success(()),
+			None => Err(Failure::BadRequest("no account matches this id")),
 		},
 		Err(err) => {
 			error!("Updating user information failed: {}", err);
-			return Err(Failure::Unexpected);
+			Err(Failure::Unexpected)
 		}
-	};
+	}
+}
+
+/// Checks the length of a display name or bio, and screens it with the content filter. Text the filter would hold for review is refused, since profiles aren't reviewed.
+fn screen_profile_text(
+	content_filter: &ContentFilter,
+	text: &str,
+	max_length: usize,
+) -> Result<String, Failure<ProfileError>> {
+	let text = text.trim();
+
+	if text.is_empty() || text.graphemes(true).count() > max_length {
+		return Err(Failure::BadRequest("empty or oversized profile text"));
+	}
+
+	if text.chars().any(char::is_control) {
+		return Err(Failure::BadRequest("control characters in profile text"));
+	}
+
+	content_filter
+		.screen_unreviewed(text)
+		.map_err(|Rejected(category)| Failure::Expected(ProfileError::ContentRejected(category)))
+}
+
+/// Updates only the given fields of the user's profile.
+///
+/// [`year_of_study`] and [`faculty`] can be set to `null` to keep them private. [`school_id`] can't be `null`, and must be a valid school. [`display_name`] and [`bio`] can be set to `null` to remove them.
+#[patch("/users/profile/")]
+pub async fn patch_profile(
+	db: web::Data<Database>,
+	content_filter: web::Data<&'static ContentFilter>,
+	patch: web::Json<ProfilePatch>,
+	user: AuthenticatedUser,
+) -> ApiResult<(), ProfileError> {
+	let patch = patch.into_inner();
+	let mut update_doc = Document::new();
+
+	if let Some(year_of_study) = patch.year_of_study.into_change() {
+		update_doc.insert(
+			"year_of_study",
+			to_bson(&year_of_study)
+				.map_err(to_unexpected!("Converting year of study to bson failed"))?,
+		);
+	}
+
+	if let Some(faculty) = patch.faculty.into_change() {
+		update_doc.insert(
+			"faculty",
+			to_bson(&faculty).map_err(to_unexpected!("Converting faculty to bson failed"))?,
+		);
+	}
+
+	match patch.school_id {
+		Patch::Unchanged => {}
+		Patch::Clear => return Err(Failure::BadRequest("school id can't be null")),
+		Patch::Set(school_id) => {
+			validate_school_id(&db, &user, &school_id).await?;
+			update_doc.insert("school_id", school_id);
+		}
+	}
+
+	if let Some(display_name) = patch.display_name.into_change() {
+		let display_name = display_name
+			.map(|display_name| {
+				screen_profile_text(
+					&content_filter,
+					&display_name,
+					conf::DISPLAY_NAME_MAX_LENGTH,
+				)
+			})
+			.transpose()?;
+		update_doc.insert("display_name", display_name);
+	}
+
+	if let Some(bio) = patch.bio.into_change() {
+		let bio = bio
+			.map(|bio| screen_profile_text(&content_filter, &bio, conf::BIO_MAX_LENGTH))
+			.transpose()?;
+		update_doc.insert("bio", bio);
+	}
+
+	if update_doc.is_empty() {
+		return success(());
+	}
+
+	let update_result = db
+		.collection::<User>("users")
+		.update_one(
+			doc! {"_id": {"$eq": user.id}},
+			doc! {"$set": update_doc},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Updating user information failed"))?;
+
+	if update_result.matched_count == 1 {
+		success(())
+	} else {
+		Err(Failure::BadRequest("no account matches this id"))
+	}
 }
 
 /// Deletes a list of universities from a user's watched list.
@@ -170,75 +371,198 @@ pub async fn delete_watched(
 	success(())
 }
 
-/// Adds a list of universities to a user's watched list.
+#[derive(Debug, Serialize)]
+pub enum WatchedError {
+	/// The list would grow past [`conf::WATCHED_SCHOOLS_MAX`].
+	LimitExceeded,
+	/// The schools with these IDs don't exist, or are retired and can't be newly watched.
+	UnknownSchools(Vec<String>),
+	/// All the schools are already watched.
+	NoChange,
+	/// The list changed while it was being replaced. The request can be retried.
+	Conflict,
+}
+
+impl ApiError for WatchedError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::LimitExceeded => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::UnknownSchools(_) => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::NoChange => StatusCode::CONFLICT,
+			Self::Conflict => StatusCode::CONFLICT,
+		}
+	}
+}
+
+/// Finds which of the schools exist, and whether each of those is retired.
+async fn find_schools(
+	db: &Database,
+	school_ids: &[String],
+) -> Result<HashMap<String, bool>, Failure<WatchedError>> {
+	db.collection::<School>("schools")
+		.find(doc! {"_id": {"$in": school_ids}}, None)
+		.await
+		.map_err(to_unexpected!("Finding schools cursor failed"))?
+		.map_ok(|school| (school.id, school.retired))
+		.try_collect::<HashMap<String, bool>>()
+		.await
+		.map_err(to_unexpected!("Finding schools failed"))
+}
+
+/// Adds a list of universities to the end of a user's watched list.
 ///
-/// If a university already exists in the list a 400 is returned.
+/// Universities already in the list are skipped, but at least one must be new. The list can't grow past [`conf::WATCHED_SCHOOLS_MAX`]. Retired universities can't be added.
 #[post("/users/watched/")]
 pub async fn add_watched(
 	user: AuthenticatedUser,
 	db: web::Data<Database>,
-	mut new_school_ids: web::Json<Vec<String>>,
-) -> ApiResult<(), ()> {
-	new_school_ids.dedup();
-	let schools_bson =
-		to_bson(&new_school_ids).map_err(to_unexpected!("Converting schools to bson failed"))?;
+	new_school_ids: web::Json<Vec<String>>,
+) -> ApiResult<(), WatchedError> {
+	let mut school_ids = Vec::<String>::with_capacity(new_school_ids.len());
+	for school_id in new_school_ids.into_inner() {
+		if !school_ids.contains(&school_id) {
+			school_ids.push(school_id);
+		}
+	}
 
-	// Check if all passed school ids are valid
+	if school_ids.is_empty() {
+		return Err(Failure::BadRequest("no school ids"));
+	}
 
-	let filter = doc! { "_id": { "$in": &schools_bson } };
+	let found = find_schools(&db, &school_ids).await?;
+	let unknown = school_ids
+		.iter()
+		.filter(|school_id| found.get(*school_id) != Some(&false))
+		.cloned()
+		.collect::<Vec<String>>();
 
-	let possible_found_schools = db.collection::<School>("schools").find(filter, None).await;
+	if !unknown.is_empty() {
+		return failure(WatchedError::UnknownSchools(unknown));
+	}
 
-	match possible_found_schools {
-		Ok(cursor) => {
-			let items_found = cursor.count().await;
-			if items_found != new_school_ids.len() {
-				return Err(Failure::BadRequest(
-					"not all items provided are valid schools",
-				));
-			};
-		}
-		Err(err) => {
-			error!("Finding schools failed: {}", err);
-			return Err(Failure::Unexpected);
-		}
+	let watched_school_ids = doc! {
+		"$concatArrays": [
+			"$watched_school_ids",
+			{"$filter": {
+				"input": {"$literal": &school_ids},
+				"cond": {"$not": [{"$in": ["$$this", "$watched_school_ids"]}]},
+			}},
+		],
 	};
 
-	let pipeline = vec![doc! {
-		"$addFields": {
-			"watched_school_ids": {
-				"$setUnion": [ "$watched_school_ids", schools_bson ]
-			}
-		}
-	}];
+	// the limit is checked against the list as it's being updated, so that concurrent additions can't exceed it together
+	let update_result = db
+		.collection::<User>("users")
+		.update_one(
+			doc! {
+				"_id": {"$eq": user.id},
+				"$expr": {"$lte": [
+					{"$size": &watched_school_ids},
+					{"$literal": i64::try_from(conf::WATCHED_SCHOOLS_MAX).unwrap()},
+				]},
+			},
+			vec![doc! {
+				"$set": {
+					"watched_school_ids": watched_school_ids,
+				}
+			}],
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Updating user's watched schools failed"))?;
 
-	let filter = doc! {"_id": user.id};
+	if update_result.matched_count != 1 {
+		let user_exists = db
+			.collection::<User>("users")
+			.count_documents(doc! {"_id": {"$eq": user.id}}, None)
+			.await
+			.map_err(to_unexpected!("Finding user failed"))?
+			!= 0;
 
-	let possible_update_result = db
-		.collection::<User>("users")
-		.update_one(filter, pipeline, None)
-		.await;
-	match possible_update_result {
-		Ok(update_result) => {
-			if update_result.modified_count == 1 {
-				return success(());
-			} else {
-				return Err(Failure::BadRequest(
-					"too many watched universities or duplicates",
-				));
-			}
+		if user_exists {
+			failure(WatchedError::LimitExceeded)
+		} else {
+			Err(Failure::BadRequest("no account matches this id"))
 		}
-		Err(err) => {
-			error!("Updating user's watched schools failed: {}", err);
-			return Err(Failure::Unexpected);
-		}
-	};
+	} else if update_result.modified_count != 1 {
+		failure(WatchedError::NoChange)
+	} else {
+		success(())
+	}
 }
 
-#[derive(Serialize)]
-pub struct SchoolDetail {
-	pub school_id: String,
-	pub full_name: String,
+/// Replaces a user's watched list, in the given order.
+///
+/// Retired universities can only be kept, not newly added. The list can't be longer than [`conf::WATCHED_SCHOOLS_MAX`].
+#[put("/users/watched/")]
+pub async fn replace_watched(
+	user: AuthenticatedUser,
+	db: web::Data<Database>,
+	school_ids: web::Json<Vec<String>>,
+) -> ApiResult<(), WatchedError> {
+	if school_ids.iter().collect::<HashSet<&String>>().len() != school_ids.len() {
+		return Err(Failure::BadRequest("repeated school id"));
+	}
+
+	if school_ids.len() > conf::WATCHED_SCHOOLS_MAX {
+		return failure(WatchedError::LimitExceeded);
+	}
+
+	let found = find_schools(&db, &school_ids).await?;
+	let unknown = school_ids
+		.iter()
+		.filter(|school_id| !found.contains_key(*school_id))
+		.cloned()
+		.collect::<Vec<String>>();
+
+	if !unknown.is_empty() {
+		return failure(WatchedError::UnknownSchools(unknown));
+	}
+
+	let retired = school_ids
+		.iter()
+		.filter(|school_id| found[*school_id])
+		.cloned()
+		.collect::<Vec<String>>();
+
+	let mut filter = doc! {"_id": {"$eq": user.id}};
+	if !retired.is_empty() {
+		// retired schools must still be watched as the list is replaced
+		filter.insert("watched_school_ids", doc! {"$all": &retired});
+	}
+
+	let update_result = db
+		.collection::<User>("users")
+		.update_one(
+			filter,
+			doc! {"$set": {"watched_school_ids": &*school_ids}},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Replacing user's watched schools failed"))?;
+
+	if update_result.matched_count == 1 {
+		return success(());
+	}
+
+	let watched_school_ids = db
+		.collection::<User>("users")
+		.find_one(doc! {"_id": {"$eq": user.id}}, None)
+		.await
+		.map_err(to_unexpected!("Finding user failed"))?
+		.ok_or(Failure::BadRequest("no account matches this id"))?
+		.watched_school_ids;
+	let newly_watched_retired = retired
+		.into_iter()
+		.filter(|school_id| !watched_school_ids.contains(school_id))
+		.collect::<Vec<String>>();
+
+	if newly_watched_retired.is_empty() {
+		// the list changed between the update and reading it back
+		failure(WatchedError::Conflict)
+	} else {
+		failure(WatchedError::UnknownSchools(newly_watched_retired))
+	}
 }
 
 /// Gets the current list of universities the user is watching.
@@ -253,12 +577,104 @@ pub async fn get_watched(
 		.await;
 	match user {
 		Ok(possible_user) => match possible_user {
-			Some(user) => return success(Box::new(user.watched_school_ids)),
-			None => return Err(Failure::BadRequest("no account matches this id")),
+			Some(user) => success(Box::new(user.watched_school_ids)),
+			None => Err(Failure::BadRequest("no account matches this id")),
 		},
 		Err(err) => {
 			error!("Getting list of watched schools failed: {}", err);
-			return Err(Failure::Unexpected);
+			Err(Failure::Unexpected)
 		}
+	}
+}
+
+/// Normalizes words to mute, rejecting any that couldn't be matched.
+fn normalize_muted_words(words: &[String]) -> Result<Vec<String>, Failure<()>> {
+	let mut words = words
+		.iter()
+		.map(|word| word.trim().to_lowercase())
+		.collect::<Vec<String>>();
+
+	if words
+		.iter()
+		.any(|word| word.is_empty() || word.len() > conf::MUTED_WORD_MAX_SIZE)
+	{
+		return Err(Failure::BadRequest("empty or oversized muted word"));
+	}
+
+	words.sort();
+	words.dedup();
+	Ok(words)
+}
+
+/// Removes words from the user's muted words.
+#[delete("/users/muted/")]
+pub async fn delete_muted(
+	user: AuthenticatedUser,
+	db: web::Data<Database>,
+	words: web::Json<Vec<String>>,
+) -> ApiResult<(), ()> {
+	let words = normalize_muted_words(&words)?;
+	let filter = doc! {"_id": {"$eq": user.id}};
+	let update = doc! { "$pull": { "muted_words": { "$in": words } } };
+	db.collection::<User>("users")
+		.update_one(filter, update, None)
+		.await
+		.map_err(to_unexpected!("Deleting muted words failed"))?;
+	success(())
+}
+
+/// Adds words to the user's muted words. Posts containing any of them are left out of the user's feeds.
+///
+/// Words already muted are ignored. A 400 is returned if the list would grow past [`conf::MUTED_WORDS_MAX`].
+#[post("/users/muted/")]
+pub async fn add_muted(
+	user: AuthenticatedUser,
+	db: web::Data<Database>,
+	words: web::Json<Vec<String>>,
+) -> ApiResult<(), ()> {
+	let words = normalize_muted_words(&words)?;
+	let muted_words = doc! {
+		"$setUnion": [{"$ifNull": ["$muted_words", []]}, words],
 	};
+
+	let update_result = db
+		.collection::<User>("users")
+		.update_one(
+			doc! {
+				"_id": {"$eq": user.id},
+				"$expr": {"$lte": [
+					{"$size": &muted_words},
+					{"$literal": i64::try_from(conf::MUTED_WORDS_MAX).unwrap()},
+				]},
+			},
+			vec![doc! {
+				"$addFields": {
+					"muted_words": muted_words,
+				}
+			}],
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Updating user's muted words failed"))?;
+
+	if update_result.matched_count == 1 {
+		success(())
+	} else {
+		Err(Failure::BadRequest("too many muted words"))
+	}
+}
+
+/// Gets the user's muted words.
+#[get("/users/muted/")]
+pub async fn get_muted(
+	user: AuthenticatedUser,
+	db: web::Data<Database>,
+) -> ApiResult<Box<Vec<String>>, ()> {
+	let user = db
+		.collection::<User>("users")
+		.find_one(doc! {"_id": {"$eq": user.id}}, None)
+		.await
+		.map_err(to_unexpected!("Getting muted words failed"))?
+		.ok_or(Failure::BadRequest("no account matches this id"))?;
+	success(Box::new(user.muted_words))
 }
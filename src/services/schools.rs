@@ -0,0 +1,340 @@
+use actix_web::http::StatusCode;
+use actix_web::{
+	delete,
+	get,
+	patch,
+	post,
+	web,
+};
+use log::{
+	error,
+	info,
+};
+use mongodb::bson::{
+	doc,
+	to_bson,
+	Document,
+};
+use mongodb::error::{
+	ErrorKind,
+	WriteFailure,
+};
+use mongodb::Database;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+
+use crate::api_types::{
+	failure,
+	success,
+	ApiError,
+	ApiResult,
+	Failure,
+};
+use crate::auth::AdminUser;
+use crate::school_stats::PublishedStats;
+use crate::schools;
+use crate::to_unexpected;
+use crate::types::{
+	Patch,
+	School,
+	SchoolStats,
+	SupportContact,
+};
+
+/// A location, in degrees.
+#[derive(Clone, Copy, Deserialize)]
+pub struct Position {
+	pub longitude: f64,
+	pub latitude: f64,
+}
+
+impl Position {
+	fn to_geojson<E: ApiError>(self) -> Result<Document, Failure<E>> {
+		if !schools::is_valid_position(self.longitude, self.latitude) {
+			return Err(Failure::BadRequest("position out of range"));
+		}
+
+		Ok(doc! {
+			"type": "Point",
+			"coordinates": [self.longitude, self.latitude],
+		})
+	}
+}
+
+#[derive(Deserialize)]
+pub struct NewSchool {
+	/// Uppercase letters and digits, like `UVIC`. Can't be changed.
+	pub id: String,
+	pub name: String,
+	pub position: Position,
+	/// The school this is a campus of, if any. Can't be changed.
+	pub parent_id: Option<String>,
+	pub counselling: Option<SupportContact>,
+	/// The domains of the school's email addresses, at which users can verify they belong to it.
+	#[serde(default)]
+	pub email_domains: Vec<String>,
+	/// Whether users must verify they belong to the school before posting.
+	#[serde(default)]
+	pub verification_required: bool,
+}
+
+/// Changes to a school. Absent fields are left unchanged.
+#[derive(Deserialize)]
+pub struct SchoolPatch {
+	pub name: Option<String>,
+	pub position: Option<Position>,
+	#[serde(default)]
+	pub counselling: Patch<SupportContact>,
+	pub email_domains: Option<Vec<String>>,
+	pub verification_required: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub enum SchoolError {
+	AlreadyExists,
+	NotFound,
+}
+
+impl ApiError for SchoolError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::AlreadyExists => StatusCode::CONFLICT,
+			Self::NotFound => StatusCode::NOT_FOUND,
+		}
+	}
+}
+
+fn validate_name<E: ApiError>(name: &str) -> Result<&str, Failure<E>> {
+	schools::normalize_name(name).ok_or(Failure::BadRequest("invalid school name"))
+}
+
+fn validate_email_domains<E: ApiError>(domains: &[String]) -> Result<Vec<String>, Failure<E>> {
+	let mut normalized = Vec::<String>::with_capacity(domains.len());
+	for domain in domains {
+		let domain = schools::normalize_email_domain(domain)
+			.ok_or(Failure::BadRequest("invalid email domain"))?;
+		if !normalized.contains(&domain) {
+			normalized.push(domain);
+		}
+	}
+	Ok(normalized)
+}
+
+fn counselling_to_bson<E: ApiError>(
+	counselling: &SupportContact,
+) -> Result<mongodb::bson::Bson, Failure<E>> {
+	if counselling.name.trim().is_empty() {
+		return Err(Failure::BadRequest("invalid counselling contact"));
+	}
+
+	to_bson(counselling).map_err(to_unexpected!(
+		"Converting counselling contact to bson failed"
+	))
+}
+
+/// Adds a school, or a campus of a school.
+#[post("/admin/schools/")]
+pub async fn create_school(
+	db: web::Data<Database>,
+	admin: AdminUser,
+	school: web::Json<NewSchool>,
+) -> ApiResult<(), SchoolError> {
+	if !schools::is_valid_id(&school.id) {
+		return Err(Failure::BadRequest("invalid school id"));
+	}
+
+	let name = validate_name(&school.name)?;
+	let mut school_doc = doc! {
+		"_id": &school.id,
+		"name": name,
+		"position": school.position.to_geojson()?,
+		"search_keys": schools::search_keys(&school.id, name, &[]),
+		"email_domains": validate_email_domains(&school.email_domains)?,
+		"verification_required": school.verification_required,
+		"retired": false,
+	};
+
+	if let Some(parent_id) = &school.parent_id {
+		// campuses of campuses aren't allowed, and since parents can't be changed, a campus can't gain campuses later
+		let parents = db
+			.collection::<School>("schools")
+			.count_documents(
+				doc! {
+					"_id": {"$eq": parent_id},
+					"parent_id": null,
+					"retired": {"$ne": true},
+				},
+				None,
+			)
+			.await
+			.map_err(to_unexpected!("Finding parent school failed"))?;
+
+		if parents == 0 {
+			return Err(Failure::BadRequest("invalid parent school"));
+		}
+
+		school_doc.insert("parent_id", parent_id);
+	}
+
+	if let Some(counselling) = &school.counselling {
+		school_doc.insert("counselling", counselling_to_bson(counselling)?);
+	}
+
+	match db
+		.collection::<Document>("schools")
+		.insert_one(school_doc, None)
+		.await
+	{
+		Ok(_) => {
+			info!("School {} created by admin {}", school.id, admin.id);
+			success(())
+		}
+		Err(err) => match err.kind.as_ref() {
+			ErrorKind::Write(WriteFailure::WriteError(write_err)) if write_err.code == 11000 => {
+				failure(SchoolError::AlreadyExists)
+			}
+			_ => {
+				error!("Creating school failed: {}", err);
+				Err(Failure::Unexpected)
+			}
+		},
+	}
+}
+
+/// Renames or relocates a school, or changes its counselling contact, email domains or verification requirement.
+///
+/// [`counselling`] can be set to `null` to remove it.
+#[patch("/admin/schools/{school_id}")]
+pub async fn patch_school(
+	db: web::Data<Database>,
+	admin: AdminUser,
+	school_id: web::Path<String>,
+	patch: web::Json<SchoolPatch>,
+) -> ApiResult<(), SchoolError> {
+	let mut update_doc = Document::new();
+	let filter = doc! {"_id": {"$eq": school_id.as_str()}};
+	let collection = db.collection::<School>("schools");
+
+	if let Some(name) = &patch.name {
+		let name = validate_name(name)?;
+		let school = collection
+			.find_one(filter.clone(), None)
+			.await
+			.map_err(to_unexpected!("Finding school failed"))?
+			.ok_or(Failure::Expected(SchoolError::NotFound))?;
+
+		update_doc.insert("name", name);
+		update_doc.insert(
+			"search_keys",
+			schools::search_keys(&school.id, name, &school.aliases),
+		);
+	}
+
+	if let Some(position) = patch.position {
+		update_doc.insert("position", position.to_geojson()?);
+	}
+
+	if let Some(email_domains) = &patch.email_domains {
+		update_doc.insert("email_domains", validate_email_domains(email_domains)?);
+	}
+
+	if let Some(verification_required) = patch.verification_required {
+		update_doc.insert("verification_required", verification_required);
+	}
+
+	match &patch.counselling {
+		Patch::Unchanged => {}
+		Patch::Clear => {
+			update_doc.insert("counselling", None::<String>);
+		}
+		Patch::Set(counselling) => {
+			update_doc.insert("counselling", counselling_to_bson(counselling)?);
+		}
+	}
+
+	let matched_count = if update_doc.is_empty() {
+		collection
+			.count_documents(filter, None)
+			.await
+			.map_err(to_unexpected!("Finding school failed"))?
+	} else {
+		collection
+			.update_one(filter, doc! {"$set": update_doc}, None)
+			.await
+			.map_err(to_unexpected!("Updating school failed"))?
+			.matched_count
+	};
+
+	if matched_count == 1 {
+		info!("School {} updated by admin {}", school_id, admin.id);
+		success(())
+	} else {
+		failure(SchoolError::NotFound)
+	}
+}
+
+/// Retires a school.
+///
+/// The school and its campuses stop being listed and can no longer be chosen, but users and posts referring to them are kept as they are.
+#[delete("/admin/schools/{school_id}")]
+pub async fn retire_school(
+	db: web::Data<Database>,
+	admin: AdminUser,
+	school_id: web::Path<String>,
+) -> ApiResult<(), SchoolError> {
+	let update_result = db
+		.collection::<School>("schools")
+		.update_many(
+			schools::and_campuses(&school_id),
+			doc! {"$set": {"retired": true}},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Retiring school failed"))?;
+
+	if update_result.matched_count != 0 {
+		info!(
+			"School {} and {} campuses retired by admin {}",
+			school_id,
+			update_result.matched_count - 1,
+			admin.id
+		);
+		success(())
+	} else {
+		failure(SchoolError::NotFound)
+	}
+}
+
+/// Gets a school's activity statistics, as of their last refresh.
+#[get("/schools/{school_id}/stats")]
+pub async fn school_stats(
+	db: web::Data<Database>,
+	school_id: web::Path<String>,
+) -> ApiResult<PublishedStats, SchoolError> {
+	let filter = doc! {"_id": {"$eq": school_id.as_str()}};
+
+	if db
+		.collection::<School>("schools")
+		.count_documents(filter.clone(), None)
+		.await
+		.map_err(to_unexpected!("Finding school failed"))?
+		== 0
+	{
+		return failure(SchoolError::NotFound);
+	}
+
+	match db
+		.collection::<SchoolStats>("school_stats")
+		.find_one(filter, None)
+		.await
+		.map_err(to_unexpected!("Getting school stats failed"))?
+	{
+		Some(stats) => success(
+			PublishedStats::try_from(&stats)
+				.map_err(to_unexpected!("Formatting school stats failed"))?,
+		),
+		None => success(PublishedStats::empty()),
+	}
+}
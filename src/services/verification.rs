@@ -0,0 +1,323 @@
+use actix_web::http::StatusCode;
+use actix_web::{
+	post,
+	web,
+};
+use log::error;
+use mongodb::bson::{
+	doc,
+	DateTime,
+};
+use mongodb::error::{
+	ErrorKind,
+	WriteFailure,
+};
+use mongodb::options::UpdateOptions;
+use mongodb::Database;
+use serde::{
+	Deserialize,
+	Serialize,
+};
+
+use crate::api_types::{
+	failure,
+	success,
+	ApiError,
+	ApiResult,
+	Failure,
+};
+use crate::auth::AuthenticatedUser;
+use crate::mail::{
+	Email,
+	Mailer,
+};
+use crate::to_unexpected;
+use crate::types::{
+	EmailVerification,
+	School,
+	User,
+};
+use crate::verification::AddressHasher;
+use crate::{
+	conf,
+	verification,
+};
+
+#[derive(Deserialize)]
+pub struct VerificationRequest {
+	/// An address at one of the user's school's email domains.
+	pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct VerificationCode {
+	pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub enum VerificationError {
+	AlreadyVerified,
+	/// The user's school doesn't list any email domains, so its users can't be verified.
+	Unavailable,
+	/// The address isn't at one of the school's email domains.
+	WrongDomain,
+	/// A code was sent within [`conf::VERIFICATION_RESEND_INTERVAL`].
+	TooSoon,
+	/// No code was sent, or it expired or was already used.
+	NoPendingCode,
+	WrongCode,
+	/// [`conf::VERIFICATION_MAX_ATTEMPTS`] wrong codes were entered. A new code has to be requested.
+	TooManyAttempts,
+	/// The address already verified another account.
+	AddressInUse,
+}
+
+impl ApiError for VerificationError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::AlreadyVerified => StatusCode::CONFLICT,
+			Self::Unavailable => StatusCode::CONFLICT,
+			Self::WrongDomain => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::TooSoon => StatusCode::TOO_MANY_REQUESTS,
+			Self::NoPendingCode => StatusCode::NOT_FOUND,
+			Self::WrongCode => StatusCode::UNPROCESSABLE_ENTITY,
+			Self::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+			Self::AddressInUse => StatusCode::CONFLICT,
+		}
+	}
+}
+
+/// Sends a verification code to an address at the user's school, replacing any code sent before.
+///
+/// Only one code can be requested per [`conf::VERIFICATION_RESEND_INTERVAL`], and not for an address that verified another account.
+#[post("/users/verification/")]
+pub async fn request_verification(
+	db: web::Data<Database>,
+	mailer: web::Data<&'static dyn Mailer>,
+	address_hasher: web::Data<&'static AddressHasher>,
+	user: AuthenticatedUser,
+	request: web::Json<VerificationRequest>,
+) -> ApiResult<(), VerificationError> {
+	let domain = verification::email_domain(&request.email)
+		.ok_or(Failure::BadRequest("invalid email address"))?;
+
+	let user = db
+		.collection::<User>("users")
+		.find_one(doc! {"_id": {"$eq": user.id}}, None)
+		.await
+		.map_err(to_unexpected!("Finding user failed"))?
+		.ok_or(Failure::BadRequest("no account matches this id"))?;
+
+	if user.is_verified() {
+		return failure(VerificationError::AlreadyVerified);
+	}
+
+	let school = db
+		.collection::<School>("schools")
+		.find_one(doc! {"_id": {"$eq": &user.school_id}}, None)
+		.await
+		.map_err(to_unexpected!("Finding user's school failed"))?
+		.ok_or(Failure::BadRequest("invalid school id"))?;
+
+	// campuses share the email domains of their parent, unless they list their own
+	let email_domains = match &school.parent_id {
+		Some(parent_id) if school.email_domains.is_empty() => db
+			.collection::<School>("schools")
+			.find_one(doc! {"_id": {"$eq": parent_id}}, None)
+			.await
+			.map_err(to_unexpected!("Finding user's parent school failed"))?
+			.map(|parent| parent.email_domains)
+			.unwrap_or_default(),
+		_ => school.email_domains,
+	};
+
+	if email_domains.is_empty() {
+		return failure(VerificationError::Unavailable);
+	}
+
+	if !verification::is_school_domain(&domain, &email_domains) {
+		return failure(VerificationError::WrongDomain);
+	}
+
+	let email_hash = address_hasher.hash(&verification::normalize_address(&request.email, &domain));
+	let users_with_address = db
+		.collection::<User>("users")
+		.count_documents(
+			doc! {
+				"_id": {"$ne": user.id},
+				"verified_email_hash": {"$eq": &email_hash},
+			},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Finding users verified with address failed"))?;
+
+	if users_with_address != 0 {
+		return failure(VerificationError::AddressInUse);
+	}
+
+	let code = verification::generate_code();
+	let now = DateTime::now();
+	let resend_cutoff = DateTime::from_millis(
+		now.timestamp_millis()
+			- i64::try_from(conf::VERIFICATION_RESEND_INTERVAL.as_millis()).unwrap(),
+	);
+	let verifications = db.collection::<EmailVerification>("email_verifications");
+
+	// a code sent too recently doesn't match the filter, so the upsert collides with it
+	match verifications
+		.update_one(
+			doc! {
+				"_id": {"$eq": user.id},
+				"sent_at": {"$lte": resend_cutoff},
+			},
+			doc! {"$set": {
+				"school_id": &school.id,
+				"email_hash": &email_hash,
+				"code": &code,
+				"attempts": 0_u32,
+				"sent_at": now,
+				"expires_at": DateTime::from_millis(
+					now.timestamp_millis()
+						+ i64::try_from(conf::VERIFICATION_CODE_TTL.as_millis()).unwrap(),
+				),
+			}},
+			UpdateOptions::builder().upsert(true).build(),
+		)
+		.await
+	{
+		Ok(_) => {}
+		Err(err) => match err.kind.as_ref() {
+			ErrorKind::Write(WriteFailure::WriteError(write_err)) if write_err.code == 11000 => {
+				return failure(VerificationError::TooSoon);
+			}
+			_ => {
+				error!("Storing verification code failed: {}", err);
+				return Err(Failure::Unexpected);
+			}
+		},
+	}
+
+	let email = Email {
+		to: request.into_inner().email,
+		subject: format!("Your {} verification code", school.name),
+		body: format!(
+			"Your code is {}. It expires in {} minutes.\n\nIf you didn't ask for a code, you can ignore this email.\n",
+			code,
+			conf::VERIFICATION_CODE_TTL.as_secs() / 60,
+		),
+	};
+	let mailer: &'static dyn Mailer = **mailer;
+
+	if let Err(err) = web::block(move || mailer.send(&email))
+		.await
+		.map_err(|err| err.to_string())
+		.and_then(|result| result.map_err(|err| err.to_string()))
+	{
+		error!("Sending verification code failed: {}", err);
+
+		// so that the user can try again right away
+		if let Err(err) = verifications
+			.delete_one(
+				doc! {
+					"_id": {"$eq": user.id},
+					"code": {"$eq": &code},
+				},
+				None,
+			)
+			.await
+		{
+			error!("Deleting unsent verification code failed: {}", err);
+		}
+
+		return Err(Failure::Unexpected);
+	}
+
+	success(())
+}
+
+/// Verifies that the user belongs to their school with the code sent to their school email address.
+#[post("/users/verification/confirm")]
+pub async fn confirm_verification(
+	db: web::Data<Database>,
+	user: AuthenticatedUser,
+	request: web::Json<VerificationCode>,
+) -> ApiResult<(), VerificationError> {
+	let verifications = db.collection::<EmailVerification>("email_verifications");
+	let pending = verifications
+		.find_one(
+			doc! {
+				"_id": {"$eq": user.id},
+				"expires_at": {"$gt": DateTime::now()},
+				// codes sent before addresses were hashed can't be used
+				"email_hash": {"$exists": true},
+			},
+			None,
+		)
+		.await
+		.map_err(to_unexpected!("Finding verification code failed"))?
+		.ok_or(Failure::Expected(VerificationError::NoPendingCode))?;
+
+	if pending.attempts >= conf::VERIFICATION_MAX_ATTEMPTS {
+		return failure(VerificationError::TooManyAttempts);
+	}
+
+	let filter = doc! {
+		"_id": {"$eq": user.id},
+		"code": {"$eq": &pending.code},
+		"attempts": {"$lt": conf::VERIFICATION_MAX_ATTEMPTS},
+	};
+
+	if !verification::code_matches(&pending.code, &request.code) {
+		verifications
+			.update_one(filter, doc! {"$inc": {"attempts": 1_u32}}, None)
+			.await
+			.map_err(to_unexpected!("Counting verification attempt failed"))?;
+		return failure(VerificationError::WrongCode);
+	}
+
+	// guards against the code being replaced, used or guessed at concurrently
+	let deletion = verifications
+		.delete_one(filter, None)
+		.await
+		.map_err(to_unexpected!("Using verification code failed"))?;
+
+	if deletion.deleted_count != 1 {
+		return failure(VerificationError::NoPendingCode);
+	}
+
+	// the unique index on `verified_email_hash` guards against the address verifying another account since the code was sent
+	let update_result = match db
+		.collection::<User>("users")
+		.update_one(
+			doc! {
+				"_id": {"$eq": user.id},
+				"school_id": {"$eq": &pending.school_id},
+			},
+			doc! {"$set": {
+				"verified_school_id": &pending.school_id,
+				"verified_email_hash": &pending.email_hash,
+			}},
+			None,
+		)
+		.await
+	{
+		Ok(update_result) => update_result,
+		Err(err) => match err.kind.as_ref() {
+			ErrorKind::Write(WriteFailure::WriteError(write_err)) if write_err.code == 11000 => {
+				return failure(VerificationError::AddressInUse);
+			}
+			_ => {
+				error!("Verifying user failed: {}", err);
+				return Err(Failure::Unexpected);
+			}
+		},
+	};
+
+	// the user changed schools since the code was sent
+	if update_result.matched_count != 1 {
+		return failure(VerificationError::NoPendingCode);
+	}
+
+	success(())
+}
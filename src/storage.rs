@@ -0,0 +1,81 @@
+//! Storage for uploaded and generated objects, such as post attachments and personal data exports.
+
+use std::fs;
+use std::io::{
+	self,
+	Write,
+};
+use std::path::PathBuf;
+
+use rand::RngCore;
+
+/// Stores immutable objects by name.
+pub trait Storage: Send + Sync {
+	/// Stores an object. Storing an object under an existing name replaces it.
+	fn put(&self, name: &str, data: &[u8]) -> io::Result<()>;
+
+	/// Gets an object, or `None` if there's no object by that name.
+	fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>>;
+
+	/// Deletes an object. Deleting an object that doesn't exist succeeds.
+	fn delete(&self, name: &str) -> io::Result<()>;
+}
+
+/// Stores objects as files in a local directory.
+pub struct LocalStorage {
+	root: PathBuf,
+}
+
+impl LocalStorage {
+	pub fn new(root: PathBuf) -> io::Result<Self> {
+		fs::create_dir_all(&root)?;
+		Ok(Self { root })
+	}
+
+	fn path(&self, name: &str) -> io::Result<PathBuf> {
+		if name.is_empty()
+			|| name.starts_with('.')
+			|| !name
+				.bytes()
+				.all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-' || b == b'_')
+		{
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"invalid object name",
+			));
+		}
+
+		Ok(self.root.join(name))
+	}
+}
+
+impl Storage for LocalStorage {
+	fn put(&self, name: &str, data: &[u8]) -> io::Result<()> {
+		let path = self.path(name)?;
+
+		// write to a temporary file first, so that partially written objects are never visible
+		let mut suffix = [0_u8; 8];
+		rand::thread_rng().fill_bytes(&mut suffix);
+		let temporary_path = self.root.join(format!(".{}.{}", name, hex::encode(suffix)));
+
+		let mut file = fs::File::create(&temporary_path)?;
+		file.write_all(data)?;
+		file.sync_all()?;
+		fs::rename(&temporary_path, path)
+	}
+
+	fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+		match fs::read(self.path(name)?) {
+			Ok(data) => Ok(Some(data)),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(err),
+		}
+	}
+
+	fn delete(&self, name: &str) -> io::Result<()> {
+		match fs::remove_file(self.path(name)?) {
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+			result => result,
+		}
+	}
+}
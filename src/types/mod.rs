@@ -0,0 +1,543 @@
+#[cfg(test)]
+mod tests;
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::{
+	self,
+	FromStr,
+};
+
+use blake2::digest::consts::U16;
+use blake2::{
+	Blake2b,
+	Digest,
+};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::spec::BinarySubtype;
+use mongodb::bson::{
+	to_bson,
+	Binary,
+	Bson,
+	DateTime,
+};
+use rand::RngCore;
+use serde::{
+	Deserialize,
+	Deserializer,
+	Serialize,
+};
+
+pub use self::token::{
+	SessionToken,
+	SessionTokenHash,
+};
+use crate::conf;
+use crate::content_filter::Category;
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct Username(String);
+
+impl AsRef<str> for Username {
+	fn as_ref(&self) -> &str {
+		self.0.as_ref()
+	}
+}
+
+impl From<Username> for String {
+	fn from(username: Username) -> Self {
+		username.0
+	}
+}
+
+impl TryFrom<String> for Username {
+	type Error = UsernameInvalid;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		if (1..=conf::USERNAME_MAX_LENGTH).contains(&s.len())
+			&& s.bytes().all(|b| b.is_ascii_alphanumeric())
+		{
+			Ok(Self(s))
+		} else {
+			Err(UsernameInvalid)
+		}
+	}
+}
+
+#[derive(Clone, Copy)]
+pub struct UsernameInvalid;
+
+impl fmt::Display for UsernameInvalid {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid username")
+	}
+}
+
+#[derive(Deserialize)]
+pub struct User {
+	#[serde(rename = "_id")]
+	pub id: ObjectId,
+	pub username: Username,
+	// Year of study of the poster.
+	pub year_of_study: Option<PosterYearOfStudy>,
+	// Faculty of the poster.
+	pub faculty: Option<PosterFaculty>,
+	// School of the user.
+	pub school_id: String,
+	// Watched universities of the user
+	pub watched_school_ids: Vec<String>,
+	/// Lowercase words which exclude posts containing them from the user's feeds.
+	#[serde(default)]
+	pub muted_words: Vec<String>,
+	/// The number of posts the user has created.
+	#[serde(default)]
+	pub post_count: i64,
+	/// The number of upvotes on the user's posts.
+	#[serde(default)]
+	pub upvotes_received: i64,
+	/// The sum of the absolute scores of the user's posts.
+	#[serde(default)]
+	pub karma: i64,
+	/// Identifies the user. Must never be shown on their posts, which are anonymous.
+	#[serde(default)]
+	pub display_name: Option<String>,
+	/// Identifies the user. Must never be shown on their posts, which are anonymous.
+	#[serde(default)]
+	pub bio: Option<String>,
+	/// The school the user proved they belong to with a school email address, if any.
+	#[serde(default)]
+	pub verified_school_id: Option<String>,
+	/// The keyed hash of the school email address the user verified with, which can't verify any other account.
+	#[serde(default)]
+	#[allow(dead_code)] // mirrors the stored document
+	pub verified_email_hash: Option<String>,
+}
+
+impl User {
+	/// Whether the user proved they belong to their current school.
+	pub fn is_verified(&self) -> bool {
+		self.verified_school_id.as_ref() == Some(&self.school_id)
+	}
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Session {
+	#[serde(rename = "_id")]
+	pub id: SessionTokenHash,
+	pub user: ObjectId,
+	pub last_used: DateTime,
+}
+
+#[derive(Deserialize)]
+#[allow(dead_code)] // mirrors the stored document
+pub struct Post {
+	#[serde(rename = "_id")]
+	pub id: ObjectId,
+	pub sequential_id: i32,
+	pub owner: ObjectId,
+	/// The school of the owner at the time of posting.
+	pub school_id: String,
+	/// The year of study of the owner at the time of posting, if it doesn't identify them.
+	pub year_of_study: Option<PosterYearOfStudy>,
+	/// The faculty of the owner at the time of posting, if it doesn't identify them.
+	pub faculty: Option<PosterFaculty>,
+	pub text: String,
+	pub votes_up: i32,
+	pub votes_down: i32,
+	pub absolute_score: i32,
+	pub status: PostStatus,
+	/// Whether the text indicates self-harm.
+	#[serde(default)]
+	pub crisis_flagged: bool,
+	#[serde(default)]
+	pub attachments: Vec<Attachment>,
+	#[serde(default)]
+	pub poll: Option<Poll>,
+	/// Whether the owner had verified they belong to the school at the time of posting, and enough of the school's users had verified that showing it doesn't identify them.
+	#[serde(default)]
+	pub verified: bool,
+}
+
+/// A poll embedded in a post.
+#[derive(Deserialize, Serialize)]
+pub struct Poll {
+	pub options: Vec<PollOption>,
+	/// When the poll stops accepting votes, if ever.
+	pub closes_at: Option<DateTime>,
+}
+
+impl Poll {
+	pub fn is_closed(&self) -> bool {
+		self.closes_at
+			.is_some_and(|closes_at| closes_at <= DateTime::now())
+	}
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct PollOption {
+	pub text: String,
+	pub votes: i32,
+}
+
+/// An image attached to a post.
+#[derive(Deserialize, Serialize)]
+pub struct Attachment {
+	/// The content-addressed name of the image in storage.
+	pub name: String,
+	pub width: u32,
+	pub height: u32,
+	pub alt_text: String,
+}
+
+/// An image uploaded by a user, which they can attach to a post.
+#[derive(Deserialize, Serialize)]
+pub struct Upload {
+	pub owner: ObjectId,
+	/// The content-addressed name of the image in storage.
+	pub name: String,
+	pub width: u32,
+	pub height: u32,
+	pub created_at: DateTime,
+}
+
+/// Who a post can be seen by.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PostStatus {
+	/// Everyone.
+	Visible,
+	/// Nobody but moderators, until they've reviewed it.
+	Held,
+	/// Nobody; removed by moderators.
+	Hidden,
+	/// Nobody; removed by the owner.
+	Deleted,
+}
+
+impl From<PostStatus> for Bson {
+	fn from(status: PostStatus) -> Self {
+		to_bson(&status).expect("post statuses should serialize")
+	}
+}
+
+/// A field of a partial update, which distinguishes a missing field from `null`. Must be used with `#[serde(default)]`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Patch<T> {
+	/// The field was absent: keep the current value.
+	#[default]
+	Unchanged,
+	/// The field was `null`: remove the current value.
+	Clear,
+	/// Replace the current value.
+	Set(T),
+}
+
+impl<T> Patch<T> {
+	/// The new value of the field, if it changes.
+	pub fn into_change(self) -> Option<Option<T>> {
+		match self {
+			Self::Unchanged => None,
+			Self::Clear => Some(None),
+			Self::Set(value) => Some(Some(value)),
+		}
+	}
+}
+
+// only called for fields that are present
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		Ok(match Option::<T>::deserialize(deserializer)? {
+			None => Self::Clear,
+			Some(value) => Self::Set(value),
+		})
+	}
+}
+
+/// The various years of study the creator of a post can be.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PosterYearOfStudy {
+	One,
+	Two,
+	Three,
+	Four,
+	Five,
+	Graduate,
+	PhD,
+	Alumni,
+}
+
+/// The various faculties the creator of a post can be associated with.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PosterFaculty {
+	Business,
+	Medicine,
+	SocialScience,
+	History,
+	Engineering,
+	ComputerScience,
+	Psychology,
+	Communication,
+	Arts,
+	Education,
+}
+
+#[derive(Clone, Deserialize)]
+#[allow(dead_code)] // mirrors the stored document
+pub struct School {
+	#[serde(rename = "_id")]
+	pub id: String,
+	pub name: String,
+	pub position: Point,
+	/// The school this is a campus of, if any. Campuses can't have campuses of their own.
+	#[serde(default)]
+	pub parent_id: Option<String>,
+	/// Other names the school is known by, like abbreviations.
+	#[serde(default)]
+	pub aliases: Vec<String>,
+	/// The domains of the school's email addresses, in lowercase.
+	#[serde(default)]
+	pub email_domains: Vec<String>,
+	/// See [`crate::schools::search_keys`].
+	#[serde(default)]
+	pub search_keys: Vec<String>,
+	/// Where students can get counselling.
+	pub counselling: Option<SupportContact>,
+	/// Retired schools aren't listed, and can't be chosen by users. Users who already chose them keep them.
+	#[serde(default)]
+	pub retired: bool,
+	/// Whether users must verify they belong to the school before posting.
+	#[serde(default)]
+	pub verification_required: bool,
+}
+
+/// A GeoJSON point.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct Point {
+	/// Longitude and latitude, in degrees.
+	pub coordinates: [f64; 2],
+}
+
+/// A service people can reach out to for support.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SupportContact {
+	pub name: String,
+	pub phone: Option<String>,
+	pub url: Option<String>,
+}
+
+/// A post waiting for a decision from moderators.
+#[derive(Deserialize, Serialize)]
+pub struct Review {
+	pub post: ObjectId,
+	pub reasons: Vec<ReviewReason>,
+	/// Whether the review should come before non-urgent ones.
+	#[serde(default)]
+	pub urgent: bool,
+	pub created_at: DateTime,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewReason {
+	/// Matched a content filter rule that holds posts for review.
+	ContentFilter(Category),
+	/// Indicates self-harm.
+	CrisisLanguage,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct Vote {
+	pub post: ObjectId,
+	pub user: ObjectId,
+	pub value: i32,
+	pub updated_at: DateTime,
+}
+
+/// A post saved by a user to read later. The ID's timestamp is when it was saved.
+#[derive(Deserialize, Serialize)]
+pub struct Save {
+	#[serde(rename = "_id")]
+	pub id: ObjectId,
+	pub user: ObjectId,
+	pub post: ObjectId,
+}
+
+/// A post hidden from a user's feeds.
+#[derive(Deserialize, Serialize)]
+pub struct Hide {
+	pub user: ObjectId,
+	pub post: ObjectId,
+}
+
+/// A code sent to a school email address, which the user can enter to verify they belong to the school.
+#[derive(Deserialize)]
+#[allow(dead_code)] // mirrors the stored document
+pub struct EmailVerification {
+	/// The user.
+	#[serde(rename = "_id")]
+	pub id: ObjectId,
+	/// The user's school when the code was sent.
+	pub school_id: String,
+	/// The keyed hash of the address the code was sent to.
+	pub email_hash: String,
+	pub code: String,
+	/// The number of wrong codes entered.
+	pub attempts: u32,
+	pub sent_at: DateTime,
+	pub expires_at: DateTime,
+}
+
+/// A copy of a user's personal data, requested by them.
+#[derive(Deserialize, Serialize)]
+pub struct Export {
+	#[serde(rename = "_id")]
+	pub id: ObjectId,
+	pub user: ObjectId,
+	pub status: ExportStatus,
+	/// The name of the JSON archive in storage, once it's ready.
+	pub archive_name: Option<String>,
+	/// When the export and its download link expire.
+	pub expires_at: DateTime,
+}
+
+/// Activity at a school, as of the last refresh by [`crate::school_stats::refresh`]. Only visible posts are counted.
+#[derive(Deserialize)]
+#[allow(dead_code)] // mirrors the stored document
+pub struct SchoolStats {
+	#[serde(rename = "_id")]
+	pub school_id: String,
+	pub posts_day: i64,
+	pub posts_week: i64,
+	pub posts_all_time: i64,
+	/// The number of users who posted in the last week.
+	pub active_posters: i64,
+	/// Upvotes and downvotes on the school's posts.
+	pub votes_cast: i64,
+	/// The number of posts by each faculty, for posts that show one.
+	pub faculties: Vec<FacultyPostCount>,
+	pub refreshed_at: DateTime,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct FacultyPostCount {
+	pub faculty: PosterFaculty,
+	pub posts: i64,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportStatus {
+	Pending,
+	Ready,
+	Failed,
+}
+
+impl From<ExportStatus> for Bson {
+	fn from(status: ExportStatus) -> Self {
+		to_bson(&status).expect("export statuses should serialize")
+	}
+}
+
+/// A user's choice in the poll of a post.
+#[derive(Deserialize, Serialize)]
+pub struct PollVote {
+	pub post: ObjectId,
+	pub user: ObjectId,
+	/// The index of the chosen option.
+	pub option: u32,
+	pub updated_at: DateTime,
+}
+
+mod token {
+	use super::*;
+
+	/// A 120-bit bearer token.
+	#[derive(Deserialize, Serialize)]
+	pub struct SessionToken([u8; 24]);
+
+	impl SessionToken {
+		pub fn generate() -> Self {
+			// emphasizing and asserting that this is a safe source of random tokens
+			fn thread_crypto_rng() -> impl rand::CryptoRng + rand::Rng {
+				rand::thread_rng()
+			}
+
+			let mut rng = thread_crypto_rng();
+			let mut result = Self(Default::default());
+			rng.fill_bytes(&mut result.0);
+
+			for c in &mut result.0 {
+				*c = (*c & 0x2f) + b'A';
+			}
+
+			result
+		}
+
+		pub fn hash(&self) -> SessionTokenHash {
+			let mut hasher = Blake2b::<U16>::new();
+			hasher.update(self.0);
+			SessionTokenHash(hasher.finalize().into())
+		}
+	}
+
+	impl fmt::Display for SessionToken {
+		fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+			write!(f, "{}", str::from_utf8(&self.0).unwrap())
+		}
+	}
+
+	impl FromStr for SessionToken {
+		type Err = InvalidTokenFormat;
+
+		fn from_str(s: &str) -> Result<Self, Self::Err> {
+			if s.len() == 24 && s.bytes().all(|b| (b'a'..=b'p').contains(&(b | 32))) {
+				Ok(Self(s.as_bytes().try_into().unwrap()))
+			} else {
+				Err(InvalidTokenFormat)
+			}
+		}
+	}
+
+	#[derive(Debug)]
+	pub struct InvalidTokenFormat;
+
+	/// A 128-bit hash of a [`SessionToken`].
+	#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+	#[serde(into = "Binary", try_from = "Binary")]
+	pub struct SessionTokenHash([u8; 16]);
+
+	impl From<SessionTokenHash> for Binary {
+		fn from(hash: SessionTokenHash) -> Self {
+			Self {
+				subtype: BinarySubtype::Generic,
+				bytes: hash.0.into(),
+			}
+		}
+	}
+
+	impl From<SessionTokenHash> for Bson {
+		fn from(hash: SessionTokenHash) -> Self {
+			Self::from(Binary::from(hash))
+		}
+	}
+
+	impl TryFrom<Binary> for SessionTokenHash {
+		type Error = InvalidHashLength;
+
+		fn try_from(b: Binary) -> Result<Self, Self::Error> {
+			b.bytes.try_into().map(Self).map_err(|_| InvalidHashLength)
+		}
+	}
+
+	#[derive(Debug)]
+	pub struct InvalidHashLength;
+
+	impl fmt::Display for InvalidHashLength {
+		fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+			write!(f, "invalid hash length")
+		}
+	}
+}
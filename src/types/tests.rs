@@ -0,0 +1,64 @@
+use serde::Deserialize;
+use serde_json::from_str as from_json;
+
+use super::Patch;
+
+#[derive(Deserialize)]
+struct Update {
+	#[serde(default)]
+	name: Patch<String>,
+	#[serde(default)]
+	age: Patch<u32>,
+}
+
+#[test]
+fn test_patch_absent() {
+	let update = from_json::<Update>("{}").unwrap();
+	assert_eq!(update.name, Patch::Unchanged);
+	assert_eq!(update.age, Patch::Unchanged);
+	assert_eq!(update.name.into_change(), None);
+}
+
+#[test]
+fn test_patch_null() {
+	let update = from_json::<Update>(r#"{"name": null}"#).unwrap();
+	assert_eq!(update.name, Patch::Clear);
+	assert_eq!(update.age, Patch::Unchanged);
+	assert_eq!(update.name.into_change(), Some(None));
+}
+
+#[test]
+fn test_patch_value() {
+	let update = from_json::<Update>(r#"{"name": "Jo", "age": 20}"#).unwrap();
+	assert_eq!(update.name, Patch::Set(String::from("Jo")));
+	assert_eq!(update.age, Patch::Set(20));
+	assert_eq!(update.name.into_change(), Some(Some(String::from("Jo"))));
+}
+
+#[test]
+fn test_patch_invalid() {
+	assert!(from_json::<Update>(r#"{"age": "twenty"}"#).is_err());
+	assert!(from_json::<Update>(r#"{"age": -1}"#).is_err());
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+struct DefaultedUpdate {
+	name: Patch<String>,
+	age: Patch<u32>,
+}
+
+#[test]
+fn test_patch_container_default() {
+	assert_eq!(
+		from_json::<DefaultedUpdate>("{}").unwrap(),
+		DefaultedUpdate::default()
+	);
+	assert_eq!(
+		from_json::<DefaultedUpdate>(r#"{"name": null, "age": 3}"#).unwrap(),
+		DefaultedUpdate {
+			name: Patch::Clear,
+			age: Patch::Set(3),
+		}
+	);
+}
@@ -0,0 +1,77 @@
+//! Verifying that users belong to their school, by sending a one-time code to an address at one of the school's email domains.
+//!
+//! Addresses are only used to send the code. Only a keyed hash of each verified address is stored, so that an address can't verify more than one account without being recoverable from the database.
+
+#[cfg(test)]
+mod tests;
+
+use rand::Rng;
+use ring::{
+	constant_time,
+	hmac,
+};
+
+use crate::conf;
+use crate::schools::normalize_email_domain;
+
+/// The lowercase domain of an email address, or `None` if it isn't a plausible address.
+pub fn email_domain(address: &str) -> Option<String> {
+	if address.len() > conf::EMAIL_ADDRESS_MAX_SIZE {
+		return None;
+	}
+
+	let (local_part, domain) = address.rsplit_once('@')?;
+	if local_part.is_empty()
+		|| local_part
+			.chars()
+			.any(|c| c.is_whitespace() || c.is_control())
+		|| local_part.ends_with('@')
+	{
+		return None;
+	}
+
+	normalize_email_domain(domain).filter(|normalized| normalized.len() == domain.len())
+}
+
+/// Normalizes an address with a valid [`email_domain`] so that variants reaching the same mailbox, like `Jo+spam@UVic.ca` for `jo@uvic.ca`, are the same.
+pub fn normalize_address(address: &str, domain: &str) -> String {
+	let (local_part, _) = address.rsplit_once('@').unwrap();
+	let local_part = local_part
+		.split_once('+')
+		.map_or(local_part, |(local_part, _tag)| local_part);
+	format!("{}@{}", local_part.to_lowercase(), domain)
+}
+
+/// Hashes normalized addresses with a secret key, so that they can be compared without being stored.
+pub struct AddressHasher(hmac::Key);
+
+impl AddressHasher {
+	pub fn new(key: &[u8; 32]) -> Self {
+		Self(hmac::Key::new(hmac::HMAC_SHA256, key))
+	}
+
+	/// The hex-encoded hash of an address, which must already be normalized.
+	pub fn hash(&self, normalized_address: &str) -> String {
+		hex::encode(hmac::sign(&self.0, normalized_address.as_bytes()))
+	}
+}
+
+/// Whether an email domain is one of a school's, or a subdomain of one, like `student.uvic.ca` for `uvic.ca`.
+pub fn is_school_domain(domain: &str, school_domains: &[String]) -> bool {
+	school_domains.iter().any(|school_domain| {
+		domain == school_domain
+			|| domain
+				.strip_suffix(school_domain.as_str())
+				.is_some_and(|subdomain| subdomain.ends_with('.'))
+	})
+}
+
+/// Generates a six-digit code.
+pub fn generate_code() -> String {
+	format!("{:06}", rand::thread_rng().gen_range(0..1_000_000))
+}
+
+/// Whether a code entered by a user is the one sent to them, ignoring surrounding whitespace.
+pub fn code_matches(sent: &str, entered: &str) -> bool {
+	constant_time::verify_slices_are_equal(sent.as_bytes(), entered.trim().as_bytes()).is_ok()
+}
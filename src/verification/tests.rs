@@ -0,0 +1,68 @@
+use super::{
+	code_matches,
+	email_domain,
+	generate_code,
+	is_school_domain,
+	normalize_address,
+	AddressHasher,
+};
+
+fn normalize(address: &str) -> String {
+	normalize_address(address, &email_domain(address).unwrap())
+}
+
+#[test]
+fn test_email_domain() {
+	assert_eq!(email_domain("jo@UVic.ca").as_deref(), Some("uvic.ca"));
+	assert_eq!(email_domain("\"a@b\"@uvic.ca").as_deref(), Some("uvic.ca"));
+	assert_eq!(email_domain("jo@localhost"), None);
+	assert_eq!(email_domain("@uvic.ca"), None);
+	assert_eq!(email_domain("jo@@uvic.ca"), None);
+	assert_eq!(email_domain("j o@uvic.ca"), None);
+	assert_eq!(email_domain("jo@ uvic.ca"), None);
+	assert_eq!(email_domain("uvic.ca"), None);
+	assert_eq!(email_domain(&format!("{}@uvic.ca", "a".repeat(250))), None);
+}
+
+#[test]
+fn test_is_school_domain() {
+	let domains = [String::from("uvic.ca"), String::from("ubc.ca")];
+	assert!(is_school_domain("uvic.ca", &domains));
+	assert!(is_school_domain("student.ubc.ca", &domains));
+	assert!(!is_school_domain("notuvic.ca", &domains));
+	assert!(!is_school_domain("uvic.ca.example", &domains));
+	assert!(!is_school_domain("uvic.ca", &[]));
+}
+
+#[test]
+fn test_code() {
+	let code = generate_code();
+	assert_eq!(code.len(), 6);
+	assert!(code.bytes().all(|b| b.is_ascii_digit()));
+
+	assert!(code_matches("012345", "012345"));
+	assert!(code_matches("012345", " 012345\n"));
+	assert!(!code_matches("012345", "12345"));
+	assert!(!code_matches("012345", "012346"));
+	assert!(!code_matches("012345", ""));
+}
+
+#[test]
+fn test_normalize_address() {
+	assert_eq!(normalize("jo@uvic.ca"), "jo@uvic.ca");
+	assert_eq!(normalize("Jo@UVic.ca"), "jo@uvic.ca");
+	assert_eq!(normalize("jo+spam@uvic.ca"), "jo@uvic.ca");
+	assert_eq!(normalize("jo+a+b@uvic.ca"), "jo@uvic.ca");
+	assert_eq!(normalize("jo@student.uvic.ca"), "jo@student.uvic.ca");
+	assert_ne!(normalize("jo@uvic.ca"), normalize("jo.b@uvic.ca"));
+}
+
+#[test]
+fn test_address_hasher() {
+	let hasher = AddressHasher::new(&[1; 32]);
+	let hash = hasher.hash("jo@uvic.ca");
+	assert_eq!(hash.len(), 64);
+	assert_eq!(hasher.hash("jo@uvic.ca"), hash);
+	assert_ne!(hasher.hash("al@uvic.ca"), hash);
+	assert_ne!(AddressHasher::new(&[2; 32]).hash("jo@uvic.ca"), hash);
+}